@@ -2,7 +2,7 @@
 
 use core::fmt::Debug;
 use peekread::{PeekRead, BufPeekReader, SeekPeekReader, PeekCursor};
-use std::io::{BufRead, Read, Seek, Result, SeekFrom, Cursor};
+use std::io::{BufRead, Read, Seek, Result, SeekFrom, Cursor, Take};
 use libfuzzer_sys::arbitrary::{self, Arbitrary};
 
 mod make_as_trait_impl;
@@ -11,7 +11,8 @@ mod make_as_trait_impl;
 pub enum Peeker {
     Buf,
     Seek,
-    Cursor
+    Cursor,
+    Take(u16),
 }
 
 #[derive(Arbitrary, Debug)]
@@ -27,6 +28,7 @@ pub enum TopLevelOp {
     SeqWithPeek(Vec<Op>),
     SeqWithoutPeek(Vec<Op>),
     Unread(Vec<u8>),
+    ConsumePrefix(Vec<u8>),
 }
 
 #[derive(Arbitrary, Debug, Clone)]
@@ -65,12 +67,14 @@ impl<T: AsRef<[u8]>> HasSeek for Cursor<T> { }
 impl<'a> HasSeek for PeekCursor<'a> { }
 impl<T: Seek + Read> HasSeek for SeekPeekReader<T> { }
 impl<T> AsSeek for BufPeekReader<T> { }
+impl<T> AsSeek for Take<T> { }
 
 make_as_trait!(BufRead);
 impl<T: AsRef<[u8]>> HasBufRead for Cursor<T> { }
 impl<'a> HasBufRead for PeekCursor<'a> { }
 impl<T: Read> HasBufRead for BufPeekReader<T> { }
 impl<T> AsBufRead for SeekPeekReader<T> { }
+impl<T: Read> HasBufRead for Take<BufPeekReader<T>> { }
 
 
 fn assert_reseq<T: Eq + Debug>(x: Result<T>, y: Result<T>) {
@@ -144,8 +148,10 @@ fn check_ops<T: Read + AsSeek + AsBufRead, U: Read + AsSeek + AsBufRead>(ops: &[
                     let peek_buf = peekcursor.fill_buf().unwrap();
                     ref_bufsize = ref_buf.len();
                     peek_bufsize = peek_buf.len();
-                    let n = ref_bufsize.min(peek_bufsize);
-                    assert_eq!(&ref_buf[..n], &peek_buf[..n]);
+                    // The reference returns all remaining data, so anything
+                    // longer would contain bytes that aren't in the stream.
+                    assert!(peek_bufsize <= ref_bufsize);
+                    assert_eq!(&ref_buf[..peek_bufsize], &peek_buf[..]);
                 },
                 Op::Consume(n) => {
                     let n = n.min(ref_bufsize).min(peek_bufsize);
@@ -166,10 +172,16 @@ fuzz_target!(|data: Target| {
 
     let mut seek_reference = Cursor::new(data.refdat.clone());
     let mut buf_reference = Cursor::new(data.refdat.clone());
+    let take_limit = match data.peeker {
+        Peeker::Take(limit) => limit as usize,
+        _ => 0,
+    };
+    let mut take_reference = Cursor::new(data.refdat.iter().copied().take(take_limit).collect::<Vec<u8>>());
     let mut cursor_reference = Cursor::new(data.refdat);
     let mut seek_peeked = SeekPeekReader::new(seek_reference.clone());
     let mut buf_peeked = BufPeekReader::new(buf_reference.clone());
     let mut cursor_peeked = cursor_reference.clone();
+    let mut take_peeked = BufPeekReader::new(cursor_reference.clone()).take(take_limit as u64);
 
     for top_level_op in &data.top_level_ops {
         match top_level_op {
@@ -178,13 +190,16 @@ fuzz_target!(|data: Target| {
                 let mut seek_rest = Vec::new();
                 let mut buf_rest = Vec::new();
                 let mut cursor_rest = Vec::new();
+                let mut take_rest = Vec::new();
                 seek_reference.clone().read_to_end(&mut seek_rest).unwrap();
                 buf_reference.clone().read_to_end(&mut buf_rest).unwrap();
                 cursor_reference.clone().read_to_end(&mut cursor_rest).unwrap();
+                take_reference.clone().read_to_end(&mut take_rest).unwrap();
                 match data.peeker {
                     Peeker::Seek => check_ops(&ops, &mut Cursor::new(seek_rest), &mut seek_peeked.peek()),
                     Peeker::Buf => check_ops(&ops, &mut Cursor::new(buf_rest), &mut buf_peeked.peek()),
                     Peeker::Cursor => check_ops(&ops, &mut Cursor::new(cursor_rest), &mut cursor_peeked.peek()),
+                    Peeker::Take(_) => check_ops(&ops, &mut Cursor::new(take_rest), &mut take_peeked.peek()),
                 };
             },
             TopLevelOp::SeqWithoutPeek(ops) => {
@@ -193,6 +208,7 @@ fuzz_target!(|data: Target| {
                     Peeker::Seek => check_ops(&ops, &mut seek_reference, &mut seek_peeked),
                     Peeker::Buf => check_ops(&ops, &mut buf_reference, &mut buf_peeked),
                     Peeker::Cursor => check_ops(&ops, &mut cursor_reference, &mut cursor_peeked),
+                    Peeker::Take(_) => check_ops(&ops, &mut take_reference, &mut take_peeked),
                 };
             },
             TopLevelOp::Unread(data) => {
@@ -204,6 +220,15 @@ fuzz_target!(|data: Target| {
                 buf_rest.splice(0..0, data.iter().copied());
                 buf_reference = Cursor::new(buf_rest);
             }
+            TopLevelOp::ConsumePrefix(prefix) => {
+                println!("consume prefix {:?}", prefix);
+                match data.peeker {
+                    Peeker::Seek => assert_reseq(seek_reference.consume_prefix(prefix), seek_peeked.consume_prefix(prefix)),
+                    Peeker::Buf => assert_reseq(buf_reference.consume_prefix(prefix), buf_peeked.consume_prefix(prefix)),
+                    Peeker::Cursor => assert_reseq(cursor_reference.consume_prefix(prefix), cursor_peeked.consume_prefix(prefix)),
+                    Peeker::Take(_) => assert_reseq(take_reference.consume_prefix(prefix), take_peeked.consume_prefix(prefix)),
+                };
+            }
         }
     }
 });
@@ -0,0 +1,344 @@
+use std::collections::VecDeque;
+use std::convert::TryInto;
+use std::io::{Read, Result, SeekFrom};
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use tokio::io::{AsyncBufRead, AsyncRead, ReadBuf};
+#[cfg(feature = "tokio-net")]
+use tokio::net::TcpStream;
+
+use crate::detail::{AsyncPeekReadImpl, PeekCursorState};
+use crate::util::seek_add_offset;
+#[cfg(doc)]
+use crate::BufPeekReader;
+use crate::{AsyncPeekCursor, AsyncPeekRead};
+
+/// A wrapper for an [`AsyncRead`] stream that implements [`AsyncPeekRead`]
+/// using a buffer to store peeked data. The asynchronous counterpart of
+/// [`BufPeekReader`].
+///
+/// ```
+/// # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+/// use peekread::{AsyncBufPeekReader, AsyncPeekRead};
+/// use tokio::io::AsyncReadExt;
+/// let mut f = AsyncBufPeekReader::new(&b"hello world"[..]);
+/// let mut buf = [0; 5];
+/// f.peek().read_exact(&mut buf).await?;
+/// assert_eq!(&buf, b"hello");
+/// let mut s = String::new();
+/// f.read_to_string(&mut s).await?;
+/// assert_eq!(s, "hello world");
+/// # Ok::<(), std::io::Error>(())
+/// # }).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct AsyncBufPeekReader<R> {
+    // Where we store the peeked but not yet read data.
+    buf_storage: VecDeque<u8>,
+    // A vec used for temporary storage.
+    tmp: Vec<u8>,
+    min_read_size: usize,
+    // Set by set_socket_peeks to serve peeks past the buffered data from the
+    // socket's receive queue.
+    socket_peek_fn: Option<SocketPeekFn<R>>,
+    inner: R,
+}
+
+type SocketPeekFn<R> = fn(&R, &mut Context<'_>, &mut ReadBuf<'_>) -> Poll<Result<usize>>;
+
+impl<R: AsyncRead + Unpin> AsyncBufPeekReader<R> {
+    const DEFAULT_MIN_READ_SIZE: usize = 8 * 1024;
+    const MIN_READ_TO_END: usize = 32;
+    // The most we read at once to fill a request, so that a peek far ahead
+    // doesn't allocate all of it up front.
+    const MAX_REQUEST_READ_SIZE: usize = 64 * 1024;
+    // How far past the buffered data we peek the socket's receive queue,
+    // further than that we buffer instead.
+    const SOCKET_PEEK_WINDOW: usize = 64 * 1024;
+
+    /// Creates a new [`AsyncBufPeekReader`].
+    pub fn new(reader: R) -> Self {
+        Self {
+            buf_storage: VecDeque::new(),
+            tmp: Vec::new(),
+            min_read_size: Self::DEFAULT_MIN_READ_SIZE,
+            socket_peek_fn: None,
+            inner: reader,
+        }
+    }
+
+    /// Sets the minimum size used when reading from the underlying stream.
+    ///
+    /// Unlike for [`BufPeekReader`] this defaults to 8 KiB, since an
+    /// asynchronous read never waits for more data than is available.
+    pub fn set_min_read_size(&mut self, nbytes: usize) {
+        self.min_read_size = nbytes;
+    }
+
+    /// Gets the minimum read size. See [`Self::set_min_read_size`].
+    pub fn min_read_size(&self) -> usize {
+        self.min_read_size
+    }
+
+    /// Returns a reference to the internally buffered data.
+    pub fn buffer(&self) -> &VecDeque<u8> {
+        &self.buf_storage
+    }
+
+    /// Gets a reference to the underlying reader.
+    ///
+    /// It is inadvisable to directly read from the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Gets a mutable reference to the underlying reader.
+    ///
+    /// It is inadvisable to directly read from the underlying reader.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Unwraps this `AsyncBufPeekReader<R>`, returning the underlying reader.
+    ///
+    /// Note that any leftover data in the internal buffer is lost.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    // Try to fill the buffer so that it's at least nbytes in length (may
+    // fail to do so if EOF is reached - no error is reported then). Data that
+    // arrived before the stream returned pending is kept in the buffer.
+    fn poll_request_buffer(&mut self, cx: &mut Context<'_>, nbytes: usize) -> Poll<Result<()>> {
+        while self.buf_storage.len() < nbytes {
+            let read_size = (nbytes - self.buf_storage.len())
+                .min(Self::MAX_REQUEST_READ_SIZE)
+                .max(self.min_read_size);
+            self.tmp.resize(read_size, 0);
+            let mut read_buf = ReadBuf::new(&mut self.tmp);
+            ready!(Pin::new(&mut self.inner).poll_read(cx, &mut read_buf))?;
+            let read = read_buf.filled().len();
+            if read == 0 {
+                break; // EOF.
+            }
+            self.buf_storage.extend(&self.tmp[..read]);
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    // Peeks up to len bytes at peek_pos from the socket's receive queue into
+    // tmp, returning the offset of the peeked data in tmp and its length, which
+    // is only 0 at EOF. Returns None if socket peeks are disabled, peek_pos is
+    // buffered or past the peek window, or the receive queue doesn't reach
+    // peek_pos yet. The data has to be buffered then.
+    fn poll_socket_peek(
+        &mut self,
+        cx: &mut Context<'_>,
+        peek_pos: usize,
+        len: usize,
+    ) -> Poll<Result<Option<(usize, usize)>>> {
+        let peek = match self.socket_peek_fn {
+            Some(peek) => peek,
+            None => return Poll::Ready(Ok(None)),
+        };
+        let offset = match peek_pos.checked_sub(self.buf_storage.len()) {
+            Some(offset) if offset < Self::SOCKET_PEEK_WINDOW => offset,
+            _ => return Poll::Ready(Ok(None)),
+        };
+
+        let end = offset
+            .saturating_add(len.max(1))
+            .min(Self::SOCKET_PEEK_WINDOW);
+        self.tmp.resize(end, 0);
+        let mut read_buf = ReadBuf::new(&mut self.tmp);
+        let received = ready!(peek(&self.inner, cx, &mut read_buf))?;
+        if received == 0 {
+            return Poll::Ready(Ok(Some((0, 0)))); // EOF.
+        }
+        if received <= offset {
+            // Waiting for more to arrive could take forever if the receive
+            // queue is full, reading it into the buffer makes room.
+            return Poll::Ready(Ok(None));
+        }
+        Poll::Ready(Ok(Some((offset, received - offset))))
+    }
+
+    // The buffered data starting from the peek position as two slices.
+    fn peek_slices(&self, peek_pos: usize) -> (&[u8], &[u8]) {
+        let (a, b) = self.buf_storage.as_slices();
+        let first = a.get(peek_pos..).unwrap_or_default();
+        let second = b
+            .get(peek_pos.saturating_sub(a.len())..)
+            .unwrap_or_default();
+        (first, second)
+    }
+
+    fn consume_buffer(&mut self, amt: usize) {
+        self.buf_storage.drain(..amt.min(self.buf_storage.len()));
+    }
+}
+
+#[cfg(feature = "tokio-net")]
+impl AsyncBufPeekReader<TcpStream> {
+    /// Sets whether peeks past the buffered data look at the socket's
+    /// receive queue with [`TcpStream::poll_peek`] instead of reading the
+    /// data into the buffer. Defaults to `false`.
+    ///
+    /// This saves copying data into the buffer only to read it out again,
+    /// e.g. when sniffing a protocol from the first bytes of a connection.
+    /// Only the first 64 KiB past the buffered data are peeked this way.
+    /// Peeking further ahead, or ahead of what the socket has received so
+    /// far, reads the received data into the buffer as usual.
+    ///
+    /// ```
+    /// # tokio::runtime::Builder::new_current_thread().enable_io().build().unwrap().block_on(async {
+    /// use peekread::{AsyncBufPeekReader, AsyncPeekRead};
+    /// use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    /// use tokio::net::{TcpListener, TcpStream};
+    /// let listener = TcpListener::bind("127.0.0.1:0").await?;
+    /// let mut client = TcpStream::connect(listener.local_addr()?).await?;
+    /// let (server, _) = listener.accept().await?;
+    /// let mut server = AsyncBufPeekReader::new(server);
+    /// server.set_socket_peeks(true);
+    /// client.write_all(b"GET / HTTP/1.1\r\n").await?;
+    /// assert!(server.starts_with("GET ").await?);
+    /// assert!(server.buffer().is_empty());
+    /// let mut method = [0; 3];
+    /// server.read_exact(&mut method).await?;
+    /// assert_eq!(&method, b"GET");
+    /// # Ok::<(), std::io::Error>(())
+    /// # }).unwrap();
+    /// ```
+    pub fn set_socket_peeks(&mut self, enabled: bool) {
+        self.socket_peek_fn = if enabled {
+            Some(TcpStream::poll_peek)
+        } else {
+            None
+        };
+    }
+
+    /// Returns whether peeks past the buffered data look at the socket's
+    /// receive queue, see [`Self::set_socket_peeks`].
+    pub fn socket_peeks(&self) -> bool {
+        self.socket_peek_fn.is_some()
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncPeekRead for AsyncBufPeekReader<R> {
+    fn peek(&mut self) -> AsyncPeekCursor<'_> {
+        AsyncPeekCursor::new(self)
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncPeekReadImpl for AsyncBufPeekReader<R> {
+    fn poll_peek_read(
+        &mut self,
+        cx: &mut Context<'_>,
+        state: &mut PeekCursorState,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<()>> {
+        let peek_pos = state.peek_pos.try_into().unwrap_or(usize::MAX);
+        if let Some((start, len)) = ready!(self.poll_socket_peek(cx, peek_pos, buf.remaining()))? {
+            let written = len.min(buf.remaining());
+            buf.put_slice(&self.tmp[start..start + written]);
+            state.peek_pos += written as u64;
+            return Poll::Ready(Ok(()));
+        }
+
+        ready!(self.poll_request_buffer(cx, peek_pos.saturating_add(1)))?;
+        let (mut first, mut second) = self.peek_slices(peek_pos);
+        let unfilled = buf.initialize_unfilled();
+        let mut written = first.read(unfilled).unwrap(); // Can't fail.
+        written += second.read(&mut unfilled[written..]).unwrap(); // Can't fail.
+        buf.advance(written);
+        state.peek_pos += written as u64;
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_peek_fill_buf<'a>(
+        &'a mut self,
+        cx: &mut Context<'_>,
+        state: &'a mut PeekCursorState,
+    ) -> Poll<Result<&'a [u8]>> {
+        let peek_pos = state.peek_pos.try_into().unwrap_or(usize::MAX);
+        let min_read_size = self.min_read_size;
+        if let Some((start, len)) = ready!(self.poll_socket_peek(cx, peek_pos, min_read_size))? {
+            return Poll::Ready(Ok(&self.tmp[start..start + len]));
+        }
+
+        ready!(self.poll_request_buffer(cx, peek_pos.saturating_add(1)))?;
+        let (first, second) = self.peek_slices(peek_pos);
+        if !first.is_empty() {
+            Poll::Ready(Ok(first))
+        } else {
+            Poll::Ready(Ok(second))
+        }
+    }
+
+    fn peek_consume(&mut self, state: &mut PeekCursorState, amt: usize) {
+        state.peek_pos += amt as u64;
+    }
+
+    fn poll_peek_seek(
+        &mut self,
+        cx: &mut Context<'_>,
+        state: &mut PeekCursorState,
+        pos: SeekFrom,
+    ) -> Poll<Result<u64>> {
+        state.peek_pos = match pos {
+            SeekFrom::Start(offset) => offset,
+            SeekFrom::Current(offset) => seek_add_offset(state.peek_pos, offset)?,
+            SeekFrom::End(offset) => {
+                loop {
+                    let requested_buffer_size =
+                        (self.buf_storage.len() * 2).max(Self::MIN_READ_TO_END);
+                    ready!(self.poll_request_buffer(cx, requested_buffer_size))?;
+                    if self.buf_storage.len() < requested_buffer_size {
+                        break;
+                    }
+                }
+                seek_add_offset(self.buf_storage.len() as u64, offset)?
+            }
+        };
+        Poll::Ready(Ok(state.peek_pos))
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for AsyncBufPeekReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<()>> {
+        let this = self.get_mut();
+        if this.buf_storage.is_empty() {
+            return Pin::new(&mut this.inner).poll_read(cx, buf);
+        }
+
+        let (mut first, mut second) = this.buf_storage.as_slices();
+        let unfilled = buf.initialize_unfilled();
+        let mut written = first.read(unfilled).unwrap(); // Can't fail.
+        written += second.read(&mut unfilled[written..]).unwrap(); // Can't fail.
+        buf.advance(written);
+        this.consume_buffer(written);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncBufRead for AsyncBufPeekReader<R> {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<&[u8]>> {
+        let this = self.get_mut();
+        ready!(this.poll_request_buffer(cx, 1))?;
+        let (first, second) = this.buf_storage.as_slices();
+        if !first.is_empty() {
+            Poll::Ready(Ok(first))
+        } else {
+            Poll::Ready(Ok(second))
+        }
+    }
+
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        self.get_mut().consume_buffer(amt)
+    }
+}
@@ -0,0 +1,120 @@
+use std::future::Future;
+use std::io::{Result, SeekFrom};
+use std::task::{Context, Poll};
+
+use tokio::io::{AsyncRead, AsyncReadExt, ReadBuf};
+
+use crate::detail::{AsyncPeekReadImpl, PeekCursorState};
+use crate::util::seek_add_offset;
+use crate::AsyncPeekCursor;
+#[cfg(doc)]
+use crate::{AsyncBufPeekReader, PeekRead};
+
+/// The asynchronous counterpart of [`PeekRead`] for [`AsyncRead`] streams.
+///
+/// Like [`PeekRead`] it can create a separate 'peek cursor' which can go ahead
+/// of the regular read cursor, but never behind it. For most streams you will
+/// have to wrap your type in an [`AsyncBufPeekReader`].
+pub trait AsyncPeekRead: AsyncRead {
+    /// Returns an [`AsyncPeekCursor`] which implements [`AsyncRead`],
+    /// [`tokio::io::AsyncBufRead`] and [`tokio::io::AsyncSeek`], allowing you
+    /// to read and seek through the stream without affecting the read cursor
+    /// of the original stream. See [`PeekRead::peek`].
+    fn peek(&mut self) -> AsyncPeekCursor<'_>;
+
+    /// Checks if the stream starts with `bytes`, without advancing the
+    /// stream. Only waits for as many bytes as needed to tell. See
+    /// [`PeekRead::starts_with`].
+    ///
+    /// ```
+    /// # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+    /// use peekread::{AsyncBufPeekReader, AsyncPeekRead};
+    /// let mut f = AsyncBufPeekReader::new(&b"\x16\x03\x01 client hello"[..]);
+    /// if f.starts_with(b"\x16\x03").await? {
+    ///     // Hand the stream to the TLS acceptor.
+    /// }
+    /// assert!(!f.starts_with("GET ").await?);
+    /// # Ok::<(), std::io::Error>(())
+    /// # }).unwrap();
+    /// ```
+    fn starts_with<'a, B: AsRef<[u8]> + 'a>(
+        &'a mut self,
+        bytes: B,
+    ) -> impl Future<Output = Result<bool>> + 'a {
+        async move {
+            let bytes = bytes.as_ref();
+            let mut peeker = self.peek();
+            let mut buf = vec![0; bytes.len()];
+            let mut matched = 0;
+            while matched < bytes.len() {
+                let read = peeker.read(&mut buf[matched..]).await?;
+                if read == 0 || buf[matched..matched + read] != bytes[matched..matched + read] {
+                    return Ok(false);
+                }
+                matched += read;
+            }
+            Ok(true)
+        }
+    }
+}
+
+impl<T: AsyncPeekRead + Unpin + ?Sized> AsyncPeekRead for &mut T {
+    #[inline]
+    fn peek(&mut self) -> AsyncPeekCursor<'_> {
+        (**self).peek()
+    }
+}
+
+impl<T: AsyncPeekRead + Unpin + ?Sized> AsyncPeekRead for Box<T> {
+    #[inline]
+    fn peek(&mut self) -> AsyncPeekCursor<'_> {
+        (**self).peek()
+    }
+}
+
+impl AsyncPeekRead for &[u8] {
+    fn peek(&mut self) -> AsyncPeekCursor<'_> {
+        AsyncPeekCursor::new(self)
+    }
+}
+
+impl AsyncPeekReadImpl for &[u8] {
+    fn poll_peek_read(
+        &mut self,
+        _cx: &mut Context<'_>,
+        state: &mut PeekCursorState,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<()>> {
+        let data = self.get(state.peek_pos as usize..).unwrap_or_default();
+        let written = data.len().min(buf.remaining());
+        buf.put_slice(&data[..written]);
+        state.peek_pos += written as u64;
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_peek_fill_buf<'a>(
+        &'a mut self,
+        _cx: &mut Context<'_>,
+        state: &'a mut PeekCursorState,
+    ) -> Poll<Result<&'a [u8]>> {
+        Poll::Ready(Ok(self.get(state.peek_pos as usize..).unwrap_or_default()))
+    }
+
+    fn peek_consume(&mut self, state: &mut PeekCursorState, amt: usize) {
+        state.peek_pos += amt as u64;
+    }
+
+    fn poll_peek_seek(
+        &mut self,
+        _cx: &mut Context<'_>,
+        state: &mut PeekCursorState,
+        pos: SeekFrom,
+    ) -> Poll<Result<u64>> {
+        state.peek_pos = match pos {
+            SeekFrom::Start(offset) => offset,
+            SeekFrom::Current(offset) => seek_add_offset(state.peek_pos, offset)?,
+            SeekFrom::End(offset) => seek_add_offset(self.len() as u64, offset)?,
+        };
+        Poll::Ready(Ok(state.peek_pos))
+    }
+}
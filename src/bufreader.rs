@@ -1,52 +1,310 @@
-use std::collections::VecDeque;
+use std::convert::TryInto;
+#[cfg(feature = "read_buf")]
+use std::io::BorrowedCursor;
 #[cfg(doc)]
 use std::io::BufReader;
-use std::io::{BufRead, Read, Result, SeekFrom};
+use std::io::{BufRead, Error, ErrorKind, Read, Result, Seek, SeekFrom};
+use std::ops::{Deref, DerefMut};
 
-use crate::util::seek_add_offset;
+#[cfg(feature = "read_buf")]
+use crate::detail::DefaultImplPeekCursor;
+use crate::util::{label_error, read_fully, seek_add_offset, shannon_entropy};
 use crate::{
     detail::{PeekCursorState, PeekReadImpl},
-    PeekCursor, PeekRead,
+    PeekCursor, PeekRead, SeekPeekReader, Transaction,
 };
 
 /// A wrapper for a [`Read`] stream that implements [`PeekRead`] using a buffer
 /// to store peeked data.
+///
+/// Non-blocking streams are supported: data read before the stream returns
+/// [`ErrorKind::WouldBlock`] is kept in the buffer, so retrying continues
+/// where it left off. Reading from a peek cursor then returns fewer bytes
+/// than requested rather than an error, as long as some are buffered.
+///
+/// ```
+/// # use std::io::{Error, ErrorKind, Read, Result};
+/// # // Returns its chunks one by one, blocking before each.
+/// # struct NonBlocking(Vec<&'static [u8]>, bool);
+/// # impl Read for NonBlocking {
+/// #     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+/// #         self.1 = !self.1;
+/// #         if self.1 { return Err(ErrorKind::WouldBlock.into()); }
+/// #         if self.0.is_empty() { return Ok(0); }
+/// #         self.0.remove(0).read(buf)
+/// #     }
+/// # }
+/// use peekread::{BufPeekReader, PeekRead};
+/// let mut f = BufPeekReader::new(NonBlocking(vec![b"he", b"llo"], false));
+/// let mut buf = [0; 5];
+/// assert_eq!(f.peek().read(&mut buf).unwrap_err().kind(), ErrorKind::WouldBlock);
+/// assert_eq!(f.peek().read(&mut buf)?, 2);
+/// assert_eq!(f.peek().read(&mut buf)?, 5);
+/// assert_eq!(&buf, b"hello");
+/// # Ok::<(), std::io::Error>(())
+/// ```
+///
+/// The data is kept when the stream blocks partway through a peek, and
+/// isn't read again when retrying:
+///
+/// ```
+/// # use std::io::{ErrorKind, Read, Result};
+/// # // Returns its chunks one by one, `None` blocks once.
+/// # struct Script(Vec<Option<&'static [u8]>>);
+/// # impl Read for Script {
+/// #     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+/// #         if self.0.is_empty() { return Ok(0); }
+/// #         match self.0.remove(0) {
+/// #             Some(mut chunk) => chunk.read(buf),
+/// #             None => Err(ErrorKind::WouldBlock.into()),
+/// #         }
+/// #     }
+/// # }
+/// use peekread::{BufPeekReader, PeekRead};
+/// let mut f = BufPeekReader::new(Script(vec![Some(b"he"), None, Some(b"llo")]));
+/// let mut buf = [0; 5];
+/// assert_eq!(f.peek().read_exact(&mut buf).unwrap_err().kind(), ErrorKind::WouldBlock);
+/// assert_eq!(f.buffer(), b"he");
+/// assert_eq!(f.bytes_pulled(), 2);
+/// f.peek().read_exact(&mut buf)?;
+/// assert_eq!(&buf, b"hello");
+/// assert_eq!(f.bytes_pulled(), 5);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+///
+/// Reading (rather than peeking) only takes what is buffered from the
+/// buffer, the rest is read from the stream straight into the caller's
+/// buffer. Large reads are thus not copied through the buffer.
+///
+/// ```
+/// # use std::io::{Read, Result};
+/// # // Records the size of every read request.
+/// # struct Record<R>(R, Vec<usize>);
+/// # impl<R: Read> Read for Record<R> {
+/// #     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+/// #         self.1.push(buf.len());
+/// #         self.0.read(buf)
+/// #     }
+/// # }
+/// use peekread::{BufPeekReader, PeekRead};
+/// let data = vec![7; 3 << 20];
+/// let mut f = BufPeekReader::new(Record(&data[..], Vec::new()));
+/// let mut buf = vec![0; 1 << 20];
+/// f.read_exact(&mut buf)?;
+/// assert_eq!(f.get_ref().1, [1 << 20]);
+///
+/// // Only what was peeked comes from the buffer.
+/// f.peek().read_exact(&mut [0; 3])?;
+/// assert_eq!(f.read(&mut buf)?, 1 << 20);
+/// assert_eq!(f.get_ref().1[1..], [3, (1 << 20) - 3]);
+/// assert_eq!(f.buffered_lookahead(), 0);
+/// # Ok::<(), std::io::Error>(())
+/// ```
 #[derive(Debug)]
 pub struct BufPeekReader<R> {
-    // Where we store the peeked but not yet read data.
-    buf_storage: VecDeque<u8>,
-    // A vec used for temporary storage.
-    tmp: Vec<u8>,
+    // Where we store the peeked but not yet read data, starting at buf_begin.
+    // Everything before buf_begin was already read and is free space.
+    buf_storage: Vec<u8>,
+    buf_begin: usize,
+    // How many bytes at the front of the buffer were pushed by unread.
+    unread_len: usize,
     min_read_size: usize,
+    max_read_size: Option<usize>,
+    max_peek_size: Option<usize>,
+    label: String,
+    error_context: bool,
+    // Set while a LookaheadGuard is alive.
+    lookahead_limit: Option<usize>,
+    // How many bytes were read from the inner reader, or its position after
+    // seeking it.
+    inner_pos: u64,
+    bytes_pulled: u64,
+    // Set by set_seeking_peeks to serve peeks past the buffered data by
+    // seeking the inner reader.
+    seek_fn: Option<fn(&mut R, SeekFrom) -> Result<u64>>,
     inner: R,
 }
 
 impl<R: Read> BufPeekReader<R> {
     const MIN_READ_TO_END: usize = 32;
+    // How much we read at once when looking for a delimiter in read_until.
+    const READ_UNTIL_CHUNK_SIZE: usize = 8 * 1024;
+    // How much fill_buf on a peek cursor reads when peeking by seeking.
+    const SEEK_PEEK_CHUNK_SIZE: usize = 8 * 1024;
 
     /// Creates a new [`BufPeekReader`].
     pub fn new(reader: R) -> Self {
         Self {
-            buf_storage: VecDeque::new(),
-            tmp: Vec::new(),
+            buf_storage: Vec::new(),
+            buf_begin: 0,
+            unread_len: 0,
             min_read_size: 0,
+            max_read_size: None,
+            max_peek_size: None,
+            label: String::new(),
+            error_context: false,
+            lookahead_limit: None,
+            inner_pos: 0,
+            bytes_pulled: 0,
+            seek_fn: None,
             inner: reader,
         }
     }
 
+    /// Creates a new [`BufPeekReader`] with room to buffer at least
+    /// `capacity` bytes without reallocating.
+    ///
+    /// This does not change the minimum read size, see
+    /// [`Self::set_min_read_size`] if you also want to read in chunks this
+    /// big.
+    ///
+    /// ```
+    /// # use std::alloc::{GlobalAlloc, Layout, System};
+    /// # use std::io::Read;
+    /// # use std::sync::atomic::{AtomicUsize, Ordering};
+    /// # // Counts allocations and reallocations.
+    /// # struct Counting;
+    /// # static ALLOCS: AtomicUsize = AtomicUsize::new(0);
+    /// # unsafe impl GlobalAlloc for Counting {
+    /// #     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+    /// #         ALLOCS.fetch_add(1, Ordering::SeqCst);
+    /// #         System.alloc(layout)
+    /// #     }
+    /// #     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+    /// #         System.dealloc(ptr, layout)
+    /// #     }
+    /// #     unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, size: usize) -> *mut u8 {
+    /// #         ALLOCS.fetch_add(1, Ordering::SeqCst);
+    /// #         System.realloc(ptr, layout, size)
+    /// #     }
+    /// # }
+    /// # #[global_allocator]
+    /// # static GLOBAL: Counting = Counting;
+    /// # fn main() -> std::io::Result<()> {
+    /// use peekread::{BufPeekReader, PeekRead};
+    /// let mut f = BufPeekReader::with_capacity(1024, &[0u8; 4096][..]);
+    /// let allocs = ALLOCS.load(Ordering::SeqCst);
+    /// let mut buf = [0; 1024];
+    /// f.peek().read_exact(&mut buf[..512])?;
+    /// f.peek().read_exact(&mut buf)?;
+    /// assert_eq!(ALLOCS.load(Ordering::SeqCst), allocs);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_capacity(capacity: usize, reader: R) -> Self {
+        let mut this = Self::new(reader);
+        this.buf_storage = Vec::with_capacity(capacity);
+        this
+    }
+
     /// Pushes the given data into the stream at the front, pushing the read
     /// cursor back.
-    pub fn unread(&mut self, data: &[u8]) {
-        self.buf_storage.reserve(data.len());
-        for byte in data.iter().copied().rev() {
-            self.buf_storage.push_front(byte);
-        }
+    ///
+    /// If the underlying reader implements [`Seek`], unread data is discarded
+    /// when seeking the read cursor with [`Seek::seek`].
+    ///
+    /// A [`PeekCursor`] borrows the reader mutably, so no peek can be active
+    /// while unreading. The next call to [`PeekRead::peek`] starts at the read
+    /// cursor, i.e. at the start of the unread data.
+    ///
+    /// ```
+    /// # use std::io::Read;
+    /// use peekread::{BufPeekReader, PeekRead};
+    /// let mut f = BufPeekReader::new(&b"world"[..]);
+    /// f.peek().read_exact(&mut [0; 3])?;
+    /// f.unread(b"hello ");
+    /// assert!(f.starts_with("hello world")?);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn unread<B: AsRef<[u8]>>(&mut self, data: B) {
+        let data = data.as_ref();
+        self.ensure_space_at_front(data.len());
+        self.buf_begin -= data.len();
+        self.buf_storage[self.buf_begin..self.buf_begin + data.len()].copy_from_slice(data);
+        self.unread_len += data.len();
+    }
+
+    /// Pushes the given text into the stream at the front, see
+    /// [`Self::unread`].
+    pub fn unread_str(&mut self, s: &str) {
+        self.unread(s);
+    }
+
+    /// Removes the data pushed back with [`Self::unread`] that wasn't read
+    /// yet from the stream and returns it. Data read ahead from the
+    /// underlying reader is kept, so the stream continues with that.
+    ///
+    /// ```
+    /// # use std::io::Read;
+    /// use peekread::{BufPeekReader, PeekRead};
+    /// let mut f = BufPeekReader::new(&b"body"[..]);
+    /// assert!(f.starts_with("bo")?);
+    /// f.unread("header;");
+    /// f.read_exact(&mut [0; 3])?;
+    /// assert_eq!(f.drain_unread(), b"der;");
+    /// let mut s = String::new();
+    /// f.read_to_string(&mut s)?;
+    /// assert_eq!(s, "body");
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn drain_unread(&mut self) -> Vec<u8> {
+        let data = self.buffer()[..self.unread_len].to_vec();
+        self.consume(self.unread_len);
+        data
+    }
+
+    /// Starts a [`Transaction`], which reads from this reader but puts the
+    /// data back into the stream unless it is committed. Useful for
+    /// backtracking parsers that need to consume data before knowing whether
+    /// they succeed.
+    ///
+    /// ```
+    /// # use std::io::Read;
+    /// use peekread::BufPeekReader;
+    /// let mut f = BufPeekReader::new(&b"12345"[..]);
+    /// let mut tx = f.begin();
+    /// tx.read_exact(&mut [0; 3])?;
+    /// tx.rollback();
+    /// let mut tx = f.begin();
+    /// tx.read_exact(&mut [0; 2])?;
+    /// tx.commit();
+    /// let mut s = String::new();
+    /// f.read_to_string(&mut s)?;
+    /// assert_eq!(s, "345");
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn begin(&mut self) -> Transaction<'_, R> {
+        Transaction::new(self)
+    }
+
+    /// Reads `src` to the end and pushes that data into the stream at the
+    /// front, like [`Self::unread`]. Returns the number of bytes unread.
+    ///
+    /// If reading from `src` fails nothing is unread.
+    pub fn unread_from<S: Read + ?Sized>(&mut self, src: &mut S) -> Result<u64> {
+        let mut data = Vec::new();
+        src.read_to_end(&mut data)?;
+        self.unread(&data);
+        Ok(data.len() as u64)
     }
 
     /// Sets the minimum size used when reading from the underlying stream.
     /// Setting this allows for efficient buffered reads on any stream
     /// similar to [`BufReader`], but is disabled by default since doing
     /// bigger reads than requested might unnecessarily block.
+    ///
+    /// [`BufRead::fill_buf`] on an empty buffer reads at least one byte
+    /// regardless, so it only returns an empty slice at the end of the
+    /// stream.
+    ///
+    /// ```
+    /// # use std::io::BufRead;
+    /// use peekread::BufPeekReader;
+    /// let mut f = BufPeekReader::new(&b"data"[..]);
+    /// assert_eq!(f.min_read_size(), 0);
+    /// assert_eq!(f.fill_buf()?, b"d");
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
     pub fn set_min_read_size(&mut self, nbytes: usize) {
         self.min_read_size = nbytes;
     }
@@ -56,12 +314,284 @@ impl<R: Read> BufPeekReader<R> {
         self.min_read_size
     }
 
+    /// Sets the maximum size used for a single read from the underlying
+    /// stream, or `None` for no limit (the default). Peeking further ahead
+    /// than this is done using multiple reads, which bounds how long a
+    /// single read may block on a slow stream. Takes precedence over
+    /// [`Self::set_min_read_size`].
+    ///
+    /// ```
+    /// # use std::io::{Read, Result};
+    /// use peekread::{BufPeekReader, PeekRead};
+    /// // Records the largest read it was asked to do.
+    /// struct Recorder<'a>(&'a [u8], usize);
+    /// impl Read for Recorder<'_> {
+    ///     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+    ///         self.1 = self.1.max(buf.len());
+    ///         self.0.read(buf)
+    ///     }
+    /// }
+    ///
+    /// let mut f = BufPeekReader::new(Recorder(&[7u8; 100], 0));
+    /// f.set_min_read_size(64);
+    /// f.set_max_read_size(Some(16));
+    /// let mut peeked = Vec::new();
+    /// f.peek().take(50).read_to_end(&mut peeked)?;
+    /// assert_eq!(peeked, [7; 50]);
+    /// assert!(f.get_ref().1 <= 16);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn set_max_read_size(&mut self, nbytes: Option<usize>) {
+        self.max_read_size = nbytes;
+    }
+
+    /// Gets the maximum read size. See [`Self::set_max_read_size`].
+    pub fn max_read_size(&self) -> Option<usize> {
+        self.max_read_size
+    }
+
+    /// Sets the maximum number of bytes that may be buffered, or `None` for no
+    /// limit (the default). Peeking further ahead than this returns an error
+    /// instead of growing the buffer, which protects against running out of
+    /// memory when peeking into untrusted streams (e.g. seeking to the end).
+    ///
+    /// A read from a peek cursor that reaches past the maximum returns the
+    /// bytes up to it, only reads that can't return anything fail, as do
+    /// reads that need the whole buffer filled like [`Read::read_exact`].
+    ///
+    /// ```
+    /// # use std::io::{Read, Seek, SeekFrom};
+    /// use peekread::{BufPeekReader, PeekRead};
+    /// let mut f = BufPeekReader::new(&[7u8; 100][..]);
+    /// f.set_max_peek_size(Some(10));
+    /// let mut peeker = f.peek();
+    /// assert_eq!(peeker.read(&mut [0; 64])?, 10);
+    /// assert!(peeker.read(&mut [0; 64]).is_err());
+    /// drop(peeker);
+    /// assert!(f.peek().read_exact(&mut [0; 11]).is_err());
+    /// assert!(f.peek().seek(SeekFrom::End(0)).is_err());
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn set_max_peek_size(&mut self, nbytes: Option<usize>) {
+        self.max_peek_size = nbytes;
+    }
+
+    /// Gets the maximum peek size. See [`Self::set_max_peek_size`].
+    pub fn max_peek_size(&self) -> Option<usize> {
+        self.max_peek_size
+    }
+
+    /// Sets a label for this reader which is included in the messages of
+    /// errors produced by this crate, to tell streams apart. Empty by default.
+    ///
+    /// ```
+    /// # use std::io::{Seek, SeekFrom};
+    /// use peekread::{BufPeekReader, PeekRead};
+    /// let mut f = BufPeekReader::new(&b"data"[..]);
+    /// f.set_label("upload");
+    /// let err = f.peek().seek(SeekFrom::Current(-1)).unwrap_err();
+    /// assert!(err.to_string().starts_with("upload: "));
+    /// ```
+    pub fn set_label(&mut self, label: impl Into<String>) {
+        self.label = label.into();
+    }
+
+    /// Gets the label of this reader. See [`Self::set_label`].
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    /// Like [`Self::unread`], but takes the data from a [`bytes::Buf`],
+    /// copying it straight into the buffer.
+    #[cfg(feature = "bytes")]
+    pub fn unread_buf<B: bytes::Buf>(&mut self, mut data: B) {
+        let len = data.remaining();
+        self.ensure_space_at_front(len);
+        self.buf_begin -= len;
+        data.copy_to_slice(&mut self.buf_storage[self.buf_begin..self.buf_begin + len]);
+        self.unread_len += len;
+    }
+
+    /// Returns up to the next `n` bytes of the stream as [`bytes::Bytes`]
+    /// without advancing the stream. Fewer bytes are returned if the stream
+    /// ends first. The data is copied out of the buffer once.
+    #[cfg(feature = "bytes")]
+    pub fn peek_to_bytes(&mut self, n: usize) -> Result<bytes::Bytes> {
+        self.request_buffer(n)?;
+        let buffer = self.buffer();
+        Ok(bytes::Bytes::copy_from_slice(
+            &buffer[..n.min(buffer.len())],
+        ))
+    }
+
+    /// Reads from the underlying reader until at least `nbytes` are
+    /// buffered or the stream ends, and returns how many bytes are buffered.
+    ///
+    /// This is useful to read ahead once before doing many small peeks,
+    /// without changing the size of every read like
+    /// [`Self::set_min_read_size`] does. Like peeking, this fails if `nbytes`
+    /// exceeds the maximum peek size.
+    pub fn fill(&mut self, nbytes: usize) -> Result<usize> {
+        self.request_buffer(nbytes)?;
+        Ok(self.buf_len())
+    }
+
+    /// Discards all buffered data, including data pushed back with
+    /// [`Self::unread`], so that the next read or peek starts at the current
+    /// position of the underlying reader. The capacity of the buffer is kept.
+    ///
+    /// **The discarded data is lost**: use this after moving the underlying
+    /// reader through [`Self::get_mut`], when the buffered data no longer
+    /// belongs to where the stream is. [`Self::read_position`] doesn't account
+    /// for such moves.
+    ///
+    /// ```
+    /// # use std::io::{Cursor, Seek, SeekFrom};
+    /// use peekread::{BufPeekReader, PeekRead};
+    /// let mut f = BufPeekReader::new(Cursor::new("header;body"));
+    /// assert!(f.starts_with("header")?);
+    /// f.get_mut().seek(SeekFrom::Start(7))?;
+    /// f.reset_buffer();
+    /// assert!(f.starts_with("body")?);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn reset_buffer(&mut self) {
+        self.buf_storage.clear();
+        self.buf_begin = 0;
+        self.unread_len = 0;
+    }
+
+    /// Shrinks the capacity of the internal buffer as much as possible while
+    /// keeping the buffered data.
+    ///
+    /// The buffer keeps its capacity after the data in it is consumed so it
+    /// can be reused for the next peek. Call this to reclaim the memory after
+    /// peeking unusually far ahead in a long-lived reader.
+    pub fn shrink_to_fit(&mut self) {
+        self.buf_storage.drain(..self.buf_begin);
+        self.buf_begin = 0;
+        self.buf_storage.shrink_to_fit();
+    }
+
+    /// Sets whether errors when peeking mention how far past the read cursor
+    /// the peek failed, to help debug speculative parsers. The
+    /// [`ErrorKind`](std::io::ErrorKind) of errors is kept. Off by default.
+    ///
+    /// ```
+    /// # use std::io::Read;
+    /// use peekread::{BufPeekReader, PeekRead};
+    /// let mut f = BufPeekReader::new(&b"abc"[..]);
+    /// f.set_error_context(true);
+    /// let err = f.peek().read_exact(&mut [0; 4]).unwrap_err();
+    /// assert!(err.to_string().ends_with("(peeking 0 bytes past the read cursor)"));
+    /// ```
+    pub fn set_error_context(&mut self, enabled: bool) {
+        self.error_context = enabled;
+    }
+
+    /// Gets whether errors get context. See [`Self::set_error_context`].
+    pub fn error_context(&self) -> bool {
+        self.error_context
+    }
+
+    /// Returns the position of the read cursor, counted in bytes read from
+    /// the underlying stream since this reader was created. This works for
+    /// any stream, see [`Self::read_cursor_absolute_position`] for the
+    /// position in a seekable stream that didn't start at 0.
+    ///
+    /// Bytes pushed back with [`Self::unread`] aren't counted, while they are
+    /// pending this is the position of the first byte after them. After
+    /// seeking with [`Seek::seek`] this is the absolute position.
+    ///
+    /// ```
+    /// # use std::io::Read;
+    /// use peekread::{BufPeekReader, PeekRead};
+    /// let mut f = BufPeekReader::new(&b"GET / HTTP/1.1"[..]);
+    /// f.peek().read_exact(&mut [0; 8])?;
+    /// f.read_exact(&mut [0; 4])?;
+    /// assert_eq!(f.read_position(), 4);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn read_position(&self) -> u64 {
+        self.inner_pos - (self.buf_len() - self.unread_len) as u64
+    }
+
+    /// Returns the total number of bytes read from the underlying stream,
+    /// whether they were buffered or passed straight through, including
+    /// those still in the buffer. Comparing this to how much was actually
+    /// consumed shows how much is read ahead, e.g. to tune
+    /// [`Self::set_min_read_size`].
+    ///
+    /// ```
+    /// # use std::io::Read;
+    /// use peekread::{BufPeekReader, PeekRead};
+    /// let mut f = BufPeekReader::new(&[0u8; 100][..]);
+    /// f.set_min_read_size(64);
+    /// f.peek().read_exact(&mut [0; 4])?;
+    /// assert_eq!(f.bytes_pulled(), 64);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn bytes_pulled(&self) -> u64 {
+        self.bytes_pulled
+    }
+
+    /// Limits how far ahead of the read cursor peek cursors may read for as
+    /// long as the returned guard lives. A peek cursor reading or filling its
+    /// buffer past `max` bytes ahead of the read cursor gets an error of kind
+    /// [`ErrorKind::Other`] instead of reading more data, which stops a
+    /// speculative parser from buffering a large part of the stream before
+    /// failing. Reading through the guard moves the read cursor, and with it
+    /// the limit.
+    ///
+    /// ```
+    /// # use std::io::{Error, Read, Result};
+    /// # fn parse_as_png<R: Read>(mut r: R) -> Result<()> {
+    /// #     let mut sig = [0; 8];
+    /// #     r.read_exact(&mut sig)?;
+    /// #     if &sig != b"\x89PNG\r\n\x1a\n" { return Err(Error::other("not png")); }
+    /// #     Ok(())
+    /// # }
+    /// # fn parse_as_text<R: Read>(mut r: R) -> Result<String> {
+    /// #     let mut s = String::new();
+    /// #     r.read_to_string(&mut s)?;
+    /// #     Ok(s)
+    /// # }
+    /// use peekread::{BufPeekReader, PeekRead};
+    /// // A stream that never ends.
+    /// let mut f = BufPeekReader::new(std::io::repeat(b'a'));
+    /// // Every parser attempt gets a fresh guard.
+    /// assert!(parse_as_png(f.with_lookahead_guard(1024).peek()).is_err());
+    /// let err = parse_as_text(f.with_lookahead_guard(1024).peek()).unwrap_err();
+    /// assert_eq!(err.kind(), std::io::ErrorKind::Other);
+    /// assert!(f.buffer().len() <= 1024);
+    /// ```
+    pub fn with_lookahead_guard(&mut self, max: u64) -> LookaheadGuard<'_, R> {
+        let max = max.try_into().unwrap_or(usize::MAX);
+        let prev_limit = self.lookahead_limit;
+        self.lookahead_limit = Some(prev_limit.map_or(max, |prev| prev.min(max)));
+        LookaheadGuard {
+            reader: self,
+            prev_limit,
+        }
+    }
+
     /// Returns a reference to the internally buffered data.
     ///
-    /// Unlike [`BufRead::fill_buf`], this will not attempt to fill the buffer
-    /// if it is empty.
-    pub fn buffer(&self) -> &VecDeque<u8> {
-        &self.buf_storage
+    /// The buffered data is always stored contiguously, so this is all of it
+    /// in a single slice, ready for slice-based parsers. Unlike
+    /// [`BufRead::fill_buf`], this will not attempt to fill the buffer if it is
+    /// empty, see [`Self::fill`] for that.
+    ///
+    /// ```
+    /// # use std::io::Read;
+    /// use peekread::{BufPeekReader, PeekRead};
+    /// let mut f = BufPeekReader::new(&b"key=value;"[..]);
+    /// f.peek().read_exact(&mut [0; 10])?;
+    /// assert_eq!(f.buffer().iter().position(|&b| b == b'='), Some(3));
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn buffer(&self) -> &[u8] {
+        &self.buf_storage[self.buf_begin..]
     }
 
     /// Gets a reference to the underlying reader.
@@ -79,34 +609,431 @@ impl<R: Read> BufPeekReader<R> {
     }
 
     /// Unwraps this `BufPeekReader<R>`, returning the underlying reader.
+    ///
+    /// Note that any buffered data is lost, use [`Self::into_parts`] if you
+    /// need it.
     pub fn into_inner(self) -> R {
         self.inner
     }
 
+    /// Unwraps this `BufPeekReader<R>`, returning the buffered data and the
+    /// underlying reader. The remaining stream consists of the buffered data
+    /// followed by the data in the underlying reader.
+    pub fn into_parts(mut self) -> (Vec<u8>, R) {
+        self.buf_storage.drain(..self.buf_begin);
+        (self.buf_storage, self.inner)
+    }
+
+    /// Creates a new [`BufPeekReader`] from buffered data and an underlying
+    /// reader, the inverse of [`Self::into_parts`]. The buffered data is
+    /// treated as if it was read ahead from the underlying reader.
+    pub fn from_parts(buffer: Vec<u8>, reader: R) -> Self {
+        let mut this = Self::new(reader);
+        this.inner_pos = buffer.len() as u64;
+        this.buf_storage = buffer;
+        this
+    }
+
+    // Pushes back data that was read since unread_len was the given value,
+    // restoring the front of the stream exactly as it was then.
+    pub(crate) fn restore_read(&mut self, data: &[u8], unread_len: usize) {
+        self.unread(data);
+        self.unread_len = unread_len;
+    }
+
+    pub(crate) fn unread_len(&self) -> usize {
+        self.unread_len
+    }
+
+    // Adds context to an error while peeking, if enabled.
+    fn peek_error(&self, peek_pos: u64, e: Error) -> Error {
+        if !self.error_context {
+            return e;
+        }
+        let msg = format!("{} (peeking {} bytes past the read cursor)", e, peek_pos);
+        Error::new(e.kind(), msg)
+    }
+
+    // Checks a peek of len bytes at peek_pos against the lookahead limit,
+    // returning where the peek has to stop. If partial is set the peek may
+    // stop early at the limit, as long as it gets at least one byte.
+    fn lookahead_end(&self, peek_pos: usize, len: usize, partial: bool) -> Result<usize> {
+        let end = peek_pos.saturating_add(len);
+        match self.lookahead_limit {
+            Some(limit) if end > limit => {
+                if partial && peek_pos < limit {
+                    Ok(limit)
+                } else {
+                    Err(label_error(
+                        &self.label,
+                        Error::other("peeked past the lookahead limit"),
+                    ))
+                }
+            }
+            _ => Ok(end),
+        }
+    }
+
+    // Cuts a peek that may stop early, ending at end, down to the maximum
+    // peek size, as long as it still gets at least one byte. Otherwise
+    // request_buffer reports the error.
+    fn max_peek_end(&self, peek_pos: usize, end: usize) -> usize {
+        match self.max_peek_size {
+            Some(max) if end > max && peek_pos < max => max,
+            _ => end,
+        }
+    }
+
+    // Accounts for bytes read from the inner reader.
+    fn count_pulled(&mut self, nbytes: u64) {
+        self.inner_pos += nbytes;
+        self.bytes_pulled += nbytes;
+    }
+
+    // The number of buffered bytes.
+    fn buf_len(&self) -> usize {
+        self.buf_storage.len() - self.buf_begin
+    }
+
+    // Moves the buffered data to the start of the storage if at least as many
+    // bytes were already read from the front, so that appending doesn't grow
+    // the storage indefinitely. This only ever moves fewer bytes than were
+    // consumed, so it's amortized O(1) per byte.
+    fn request_space_at_end(&mut self) {
+        if self.buf_begin > 0 && self.buf_begin >= self.buf_len() {
+            self.buf_storage.drain(..self.buf_begin);
+            self.buf_begin = 0;
+        }
+    }
+
+    // Makes sure there is room for at least n bytes before buf_begin. Makes
+    // room for the size of the buffer as well so that repeated small unreads
+    // are amortized O(1) per byte.
+    fn ensure_space_at_front(&mut self, n: usize) {
+        if self.buf_begin < n {
+            let shift = n - self.buf_begin + self.buf_len();
+            self.buf_storage
+                .splice(0..0, std::iter::repeat(0).take(shift));
+            self.buf_begin += shift;
+        }
+    }
+
     // Try to fill the buffer so that it's at least nbytes in length
     // (may fail to do so if EOF is reached - no error is reported then).
+    // Reports an error if that would exceed the maximum peek size. Data read
+    // before an error, e.g. WouldBlock, stays in the buffer.
     fn request_buffer(&mut self, nbytes: usize) -> Result<()> {
-        let nbytes_needed = nbytes.saturating_sub(self.buf_storage.len());
-        if nbytes_needed > 0 {
-            let read_size = nbytes_needed.max(self.min_read_size);
-            self.inner
+        let max_peek_size = self.max_peek_size.unwrap_or(usize::MAX);
+        loop {
+            let nbytes_needed = nbytes.min(max_peek_size).saturating_sub(self.buf_len());
+            if nbytes_needed == 0 {
+                break;
+            }
+
+            let mut read_size = nbytes_needed
+                .max(self.min_read_size)
+                .min(max_peek_size - self.buf_len());
+            if let Some(max_read_size) = self.max_read_size {
+                read_size = read_size.min(max_read_size.max(1));
+            }
+            self.request_space_at_end();
+            let len = self.buf_storage.len();
+            let result = self
+                .inner
                 .by_ref()
                 .take(read_size as u64)
-                .read_to_end(&mut self.tmp)?;
-            self.buf_storage.reserve(self.tmp.len());
-            self.buf_storage.extend(self.tmp.drain(..));
+                .read_to_end(&mut self.buf_storage);
+            // Data read before an error is kept in the buffer.
+            self.count_pulled((self.buf_storage.len() - len) as u64);
+            if result? < read_size {
+                return Ok(()); // EOF.
+            }
+        }
+
+        if nbytes > self.buf_len() {
+            return Err(label_error(
+                &self.label,
+                Error::other("peeked past the maximum peek size"),
+            ));
         }
         Ok(())
     }
 
-    // The buffered data starting from the peek position as two slices.
-    fn peek_slices(&self, peek_pos: usize) -> (&[u8], &[u8]) {
-        let (a, b) = self.buf_storage.as_slices();
-        let first = a.get(peek_pos..).unwrap_or_default();
-        let second = b
-            .get(peek_pos.saturating_sub(a.len())..)
-            .unwrap_or_default();
-        (first, second)
+    // Like request_buffer, but if a non-blocking stream would block the data
+    // buffered so far is good enough, as long as there's more than min_len.
+    fn request_buffer_nonblocking(&mut self, nbytes: usize, min_len: usize) -> Result<()> {
+        match self.request_buffer(nbytes) {
+            Err(e) if e.kind() == ErrorKind::WouldBlock && self.buf_len() > min_len => Ok(()),
+            result => result,
+        }
+    }
+
+    // Does a single read of at most nbytes from the underlying reader into
+    // the buffer, returning how many bytes were read.
+    fn read_once(&mut self, nbytes: usize) -> Result<usize> {
+        self.request_space_at_end();
+        let len = self.buf_storage.len();
+        self.buf_storage.resize(len + nbytes, 0);
+        let result = self.inner.read(&mut self.buf_storage[len..]);
+        let read = *result.as_ref().unwrap_or(&0);
+        self.buf_storage.truncate(len + read);
+        self.count_pulled(read as u64);
+        result
+    }
+
+    // How far the peek position is past the buffered data, if peeks there are
+    // served by seeking the inner reader.
+    fn seek_peek_offset(&self, peek_pos: u64) -> Option<u64> {
+        self.seek_fn?;
+        peek_pos
+            .checked_sub(self.buf_len() as u64)
+            .filter(|&offset| offset > 0)
+    }
+
+    // Reads into buf from offset bytes past the buffered data by seeking the
+    // inner reader there and back, without buffering anything.
+    fn read_past_buffer(&mut self, offset: u64, buf: &mut [u8]) -> Result<usize> {
+        let seek = self.seek_fn.expect("seeking peeks are enabled");
+        let start = seek(&mut self.inner, SeekFrom::Current(0))?;
+        seek(
+            &mut self.inner,
+            SeekFrom::Start(start.saturating_add(offset)),
+        )?;
+        let result = read_fully(&mut self.inner, buf);
+        seek(&mut self.inner, SeekFrom::Start(start))?;
+        let read = result?;
+        self.bytes_pulled += read as u64;
+        Ok(read)
+    }
+
+    // Like read_past_buffer, but reads into the scratch buffer of the peek
+    // cursor at least n bytes, as far as the lookahead limit allows.
+    fn fill_past_buffer<'a>(
+        &mut self,
+        state: &'a mut PeekCursorState,
+        offset: u64,
+        n: usize,
+        partial: bool,
+    ) -> Result<&'a [u8]> {
+        let peek_pos = state.peek_pos as usize;
+        self.lookahead_end(peek_pos, n, partial)?;
+        let end = self.lookahead_end(peek_pos, n.max(Self::SEEK_PEEK_CHUNK_SIZE), true)?;
+        state.buf.resize(end - peek_pos, 0);
+        let read = self.read_past_buffer(offset, &mut state.buf)?;
+        state.buf.truncate(read);
+        Ok(&state.buf)
+    }
+
+    // The buffered data starting from the peek position.
+    fn peek_slice(&self, peek_pos: usize) -> &[u8] {
+        self.buffer().get(peek_pos..).unwrap_or_default()
+    }
+
+    // The buffered data starting from the peek position, up to end.
+    fn peek_window(&self, peek_pos: usize, end: usize) -> &[u8] {
+        let slice = self.peek_slice(peek_pos);
+        &slice[..slice.len().min(end.saturating_sub(peek_pos))]
+    }
+}
+
+/// A guard limiting how far ahead a [`BufPeekReader`] may be peeked, created
+/// by [`BufPeekReader::with_lookahead_guard`]. The limit is lifted when the
+/// guard is dropped.
+///
+/// The guard dereferences to the [`BufPeekReader`].
+#[derive(Debug)]
+pub struct LookaheadGuard<'a, R> {
+    reader: &'a mut BufPeekReader<R>,
+    prev_limit: Option<usize>,
+}
+
+impl<'a, R> Deref for LookaheadGuard<'a, R> {
+    type Target = BufPeekReader<R>;
+
+    fn deref(&self) -> &Self::Target {
+        self.reader
+    }
+}
+
+impl<'a, R> DerefMut for LookaheadGuard<'a, R> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.reader
+    }
+}
+
+impl<'a, R> Drop for LookaheadGuard<'a, R> {
+    fn drop(&mut self) {
+        self.reader.lookahead_limit = self.prev_limit;
+    }
+}
+
+impl<R: Read + Seek> BufPeekReader<R> {
+    /// Sets whether peeks that start past the buffered data are served by
+    /// seeking the underlying stream, rather than by buffering everything up
+    /// to them. Disabled by default.
+    ///
+    /// Without this, seeking a peek cursor relative to the end reads the
+    /// whole stream into the buffer, and peeking far ahead buffers all the
+    /// data in between. With this, finding the end takes a few seeks, and data
+    /// past the buffered data is read straight from the stream and seeked
+    /// back over, so the buffer doesn't grow with how far ahead you peek.
+    /// Peeks that start within the buffered data still extend the buffer.
+    /// The lookahead limit applies to both, the maximum peek size only to
+    /// buffered data.
+    ///
+    /// ```
+    /// # use std::io::{Cursor, Read, Seek, SeekFrom};
+    /// use peekread::{BufPeekReader, PeekRead};
+    /// let mut data = vec![0; 1 << 20];
+    /// data.extend_from_slice(b"TRLR");
+    /// let mut f = BufPeekReader::new(Cursor::new(data));
+    /// f.set_seeking_peeks(true);
+    /// let mut peeker = f.peek();
+    /// peeker.seek(SeekFrom::End(-4))?;
+    /// let mut trailer = [0; 4];
+    /// peeker.read_exact(&mut trailer)?;
+    /// assert_eq!(&trailer, b"TRLR");
+    /// drop(peeker);
+    /// assert_eq!(f.buffered_lookahead(), 0);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn set_seeking_peeks(&mut self, enabled: bool) {
+        self.seek_fn = if enabled { Some(R::seek) } else { None };
+    }
+
+    /// Returns whether peeks past the buffered data are served by seeking,
+    /// see [`Self::set_seeking_peeks`].
+    pub fn seeking_peeks(&self) -> bool {
+        self.seek_fn.is_some()
+    }
+
+    /// Returns the position of the read cursor in the underlying stream,
+    /// accounting for the data that was read ahead into the buffer.
+    ///
+    /// Bytes pushed back with [`Self::unread`] have no position in the
+    /// underlying stream and are not counted. While such bytes are pending
+    /// this returns the position of the first byte after them, which is where
+    /// the read cursor ends up once they are consumed.
+    pub fn read_cursor_absolute_position(&mut self) -> Result<u64> {
+        let inner_pos = self.inner.stream_position()?;
+        self.read_ahead_start(inner_pos)
+    }
+
+    // The position of the data that was read ahead into the buffer, given
+    // the position of the underlying stream.
+    fn read_ahead_start(&self, inner_pos: u64) -> Result<u64> {
+        let read_ahead = (self.buf_len() - self.unread_len) as u64;
+        inner_pos.checked_sub(read_ahead).ok_or_else(|| {
+            label_error(
+                &self.label,
+                Error::other("the underlying stream was moved before the buffered data"),
+            )
+        })
+    }
+
+    /// Converts this reader into a [`SeekPeekReader`] without losing any
+    /// data. The underlying stream is seeked back over the data that was read
+    /// ahead into the buffer, and bytes pushed back with [`Self::unread`] are
+    /// carried over. The label is kept.
+    ///
+    /// Fails if the underlying stream can't seek back, in which case the
+    /// reader is lost.
+    ///
+    /// ```
+    /// # use std::io::{Cursor, Read};
+    /// use peekread::{BufPeekReader, PeekRead};
+    /// let mut f = BufPeekReader::new(Cursor::new("hello world"));
+    /// assert!(f.starts_with("hello")?);
+    /// f.unread("why ");
+    /// let mut f = f.into_seek_reader()?;
+    /// let mut s = String::new();
+    /// f.read_to_string(&mut s)?;
+    /// assert_eq!(s, "why hello world");
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn into_seek_reader(mut self) -> Result<SeekPeekReader<R>> {
+        let read_ahead = (self.buf_len() - self.unread_len) as i64;
+        if let Err(e) = self.inner.seek(SeekFrom::Current(-read_ahead)) {
+            let msg = format!("cannot seek back over buffered data: {}", e);
+            return Err(label_error(&self.label, Error::new(e.kind(), msg)));
+        }
+        let unread = self.buffer()[..self.unread_len].to_vec();
+        let mut reader = SeekPeekReader::new(self.inner);
+        reader.unread(unread);
+        reader.set_label(self.label);
+        Ok(reader)
+    }
+}
+
+/// Bytes pushed back with [`BufPeekReader::unread`] have no position in the
+/// underlying stream. Seeking forward from the current position skips them
+/// first, seeking back or to an absolute position drops them.
+///
+/// ```
+/// # use std::io::{Cursor, Read, Seek, SeekFrom};
+/// use peekread::BufPeekReader;
+/// let data: Vec<u8> = (0..20).collect();
+/// let mut f = BufPeekReader::new(Cursor::new(data));
+/// f.read_exact(&mut [0; 10])?;
+/// f.unread(b"abc");
+/// assert_eq!(f.stream_position()?, 10);
+/// assert_eq!(f.seek(SeekFrom::Current(-1))?, 9);
+/// let mut byte = [0];
+/// f.read_exact(&mut byte)?;
+/// assert_eq!(byte, [9]);
+/// f.unread(b"abc");
+/// assert_eq!(f.seek(SeekFrom::Current(5))?, 12);
+/// f.read_exact(&mut byte)?;
+/// assert_eq!(byte, [12]);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+impl<R: Read + Seek> Seek for BufPeekReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        let inner_pos = self.inner.stream_position()?;
+        let buffered_start = self.read_ahead_start(inner_pos)?;
+        let unread_len = self.unread_len as u64;
+        let target = match pos {
+            SeekFrom::Current(offset) if offset >= 0 && offset as u64 <= self.buf_len() as u64 => {
+                // Skip ahead in the buffered data, starting with the bytes
+                // pushed back with unread, which have no stream position.
+                self.consume(offset as usize);
+                return Ok(buffered_start + (offset as u64).saturating_sub(unread_len));
+            }
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(offset) => {
+                // Seeking forward skips the unread bytes first, seeking back
+                // drops them.
+                let offset = if offset > 0 {
+                    offset.saturating_sub(unread_len as i64)
+                } else {
+                    offset
+                };
+                Some(
+                    seek_add_offset(buffered_start, offset)
+                        .map_err(|e| label_error(&self.label, e))?,
+                )
+            }
+            SeekFrom::End(_) => None,
+        };
+
+        // Seeking within the read ahead data doesn't need to touch the stream.
+        if let Some(target) = target {
+            if (buffered_start..=inner_pos).contains(&target) {
+                self.consume(self.unread_len + (target - buffered_start) as usize);
+                return Ok(target);
+            }
+        }
+
+        let new_pos = self
+            .inner
+            .seek(target.map(SeekFrom::Start).unwrap_or(pos))?;
+        self.consume(self.buf_len());
+        self.inner_pos = new_pos;
+        Ok(new_pos)
+    }
+
+    fn stream_position(&mut self) -> Result<u64> {
+        self.read_cursor_absolute_position()
     }
 }
 
@@ -114,26 +1041,182 @@ impl<R: Read> PeekRead for BufPeekReader<R> {
     fn peek(&mut self) -> PeekCursor<'_> {
         PeekCursor::new(self)
     }
+
+    fn buffered_lookahead(&self) -> usize {
+        self.buf_len()
+    }
+
+    /// Buffered bytes are dropped from the buffer without copying them, so
+    /// [`PeekRead::consume_prefix`] drops the prefix it just peeked in one
+    /// step.
+    ///
+    /// ```
+    /// # use std::io::Read;
+    /// use peekread::{BufPeekReader, PeekRead};
+    /// let mut f = BufPeekReader::new(&b"GET / HTTP/1.1"[..]);
+    /// assert!(!f.consume_prefix("POST ")?);
+    /// assert!(f.consume_prefix("GET ")?);
+    /// let mut rest = String::new();
+    /// f.read_to_string(&mut rest)?;
+    /// assert_eq!(rest, "/ HTTP/1.1");
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    fn skip(&mut self, n: u64) -> Result<u64> {
+        let buffered = (self.buf_len() as u64).min(n);
+        self.consume(buffered as usize);
+        let skipped = std::io::copy(
+            &mut self.inner.by_ref().take(n - buffered),
+            &mut std::io::sink(),
+        )?;
+        self.count_pulled(skipped);
+        Ok(buffered + skipped)
+    }
+
+    fn read_prefix(&mut self, n: usize) -> Result<Vec<u8>> {
+        if self.buf_len() < n {
+            let mut prefix = vec![0; n];
+            self.read_exact(&mut prefix)?;
+            return Ok(prefix);
+        }
+
+        // Already buffered, take it straight from the buffer.
+        let prefix = self.buffer()[..n].to_vec();
+        self.consume(n);
+        Ok(prefix)
+    }
+
+    fn peek_at(&mut self, offset: u64, buf: &mut [u8]) -> Result<usize> {
+        let gap = self.seek_peek_offset(offset);
+        let offset = offset.try_into().unwrap_or(usize::MAX);
+        let end = self.lookahead_end(offset, buf.len(), true)?;
+        if let Some(gap) = gap {
+            return self.read_past_buffer(gap, &mut buf[..end - offset]);
+        }
+        let end = self.max_peek_end(offset, end);
+        self.request_buffer(end)?;
+        Ok(self.peek_window(offset, end).read(buf).unwrap()) // Can't fail.
+    }
+
+    fn peek_ranges(
+        &mut self,
+        ranges: &[(u64, usize)],
+        out: &mut Vec<u8>,
+    ) -> Result<Vec<(usize, usize)>> {
+        // Fill the buffer up to the furthest range once, then copy the
+        // ranges out of it.
+        let to_usize = |offset: u64| offset.try_into().unwrap_or(usize::MAX);
+        let max_end = ranges
+            .iter()
+            .map(|&(offset, len)| to_usize(offset).saturating_add(len))
+            .max()
+            .unwrap_or(0);
+        let max_end = self.lookahead_end(0, max_end, true)?;
+        let max_end = self.max_peek_end(0, max_end);
+        self.request_buffer(max_end)?;
+
+        let mut spans = Vec::with_capacity(ranges.len());
+        for &(offset, len) in ranges {
+            let offset = to_usize(offset);
+            let start = out.len();
+            out.extend_from_slice(
+                self.peek_window(offset, offset.saturating_add(len).min(max_end)),
+            );
+            spans.push((start, out.len()));
+        }
+        Ok(spans)
+    }
+
+    fn peek_entropy_estimate(&mut self, sample: usize) -> Result<f64> {
+        self.request_buffer(self.max_peek_end(0, sample))?;
+        let mut counts = [0u64; 256];
+        for &byte in self.buffer().iter().take(sample) {
+            counts[byte as usize] += 1;
+        }
+        Ok(shannon_entropy(&counts))
+    }
 }
 
 impl<R: Read> PeekReadImpl for BufPeekReader<R> {
     fn peek_read(&mut self, state: &mut PeekCursorState, buf: &mut [u8]) -> Result<usize> {
-        self.request_buffer(state.peek_pos as usize + buf.len())?;
-        let (mut first, mut second) = self.peek_slices(state.peek_pos as usize);
-        let mut written = first.read(buf).unwrap(); // Can't fail.
-        written += second.read(&mut buf[written..]).unwrap(); // Can't fail.
+        let peek_pos = state.peek_pos as usize;
+        if let Some(offset) = self.seek_peek_offset(state.peek_pos) {
+            let written = self
+                .lookahead_end(peek_pos, buf.len(), true)
+                .and_then(|end| self.read_past_buffer(offset, &mut buf[..end - peek_pos]))
+                .map_err(|e| self.peek_error(state.peek_pos, e))?;
+            state.peek_pos += written as u64;
+            return Ok(written);
+        }
+
+        let end = self
+            .lookahead_end(peek_pos, buf.len(), true)
+            .map(|end| self.max_peek_end(peek_pos, end))
+            .and_then(|end| self.request_buffer_nonblocking(end, peek_pos).map(|_| end))
+            .map_err(|e| self.peek_error(state.peek_pos, e))?;
+        let written = self.peek_window(peek_pos, end).read(buf).unwrap(); // Can't fail.
         state.peek_pos += written as u64;
         Ok(written)
     }
 
-    fn peek_fill_buf(&mut self, state: &mut PeekCursorState) -> Result<&[u8]> {
-        self.request_buffer(state.peek_pos as usize + 1)?;
-        let (first, second) = self.peek_slices(state.peek_pos as usize);
-        if !first.is_empty() {
-            Ok(first)
-        } else {
-            Ok(second)
+    #[cfg(feature = "read_buf")]
+    fn peek_read_buf(
+        &mut self,
+        state: &mut PeekCursorState,
+        mut cursor: BorrowedCursor<'_>,
+    ) -> Result<()> {
+        if self.seek_peek_offset(state.peek_pos).is_some() {
+            return DefaultImplPeekCursor::new(self, state).read_buf(cursor);
         }
+
+        let peek_pos = state.peek_pos as usize;
+        let end = self
+            .lookahead_end(peek_pos, cursor.capacity(), true)
+            .map(|end| self.max_peek_end(peek_pos, end))
+            .and_then(|end| self.request_buffer_nonblocking(end, peek_pos).map(|_| end))
+            .map_err(|e| self.peek_error(state.peek_pos, e))?;
+        let window = self.peek_window(peek_pos, end);
+        let written = window.len().min(cursor.capacity());
+        cursor.append(&window[..written]);
+        state.peek_pos += written as u64;
+        Ok(())
+    }
+
+    fn peek_fill_buf<'a>(&'a mut self, state: &'a mut PeekCursorState) -> Result<&'a [u8]> {
+        if let Some(offset) = self.seek_peek_offset(state.peek_pos) {
+            let peek_pos = state.peek_pos;
+            return self
+                .fill_past_buffer(state, offset, 1, true)
+                .map_err(|e| self.peek_error(peek_pos, e));
+        }
+
+        let peek_pos = state.peek_pos as usize;
+        let end = self
+            .lookahead_end(peek_pos, 1, true)
+            .and_then(|_| self.request_buffer(peek_pos + 1))
+            .map(|_| self.lookahead_limit.unwrap_or(usize::MAX))
+            .map_err(|e| self.peek_error(state.peek_pos, e))?;
+        Ok(self.peek_window(peek_pos, end))
+    }
+
+    fn peek_fill_buf_at_least<'a>(
+        &'a mut self,
+        state: &'a mut PeekCursorState,
+        n: usize,
+    ) -> Result<&'a [u8]> {
+        if let Some(offset) = self.seek_peek_offset(state.peek_pos) {
+            let peek_pos = state.peek_pos;
+            return self
+                .fill_past_buffer(state, offset, n.max(1), false)
+                .map_err(|e| self.peek_error(peek_pos, e));
+        }
+
+        let peek_pos = state.peek_pos as usize;
+        let end = self
+            .lookahead_end(peek_pos, n.max(1), false)
+            .and_then(|end| self.request_buffer(end))
+            .map(|_| self.lookahead_limit.unwrap_or(usize::MAX))
+            .map_err(|e| self.peek_error(state.peek_pos, e))?;
+        Ok(self.peek_window(peek_pos, end))
     }
 
     fn peek_consume(&mut self, state: &mut PeekCursorState, amt: usize) {
@@ -141,10 +1224,28 @@ impl<R: Read> PeekReadImpl for BufPeekReader<R> {
     }
 
     fn peek_read_exact(&mut self, state: &mut PeekCursorState, buf: &mut [u8]) -> Result<()> {
-        self.request_buffer(state.peek_pos as usize + buf.len())?;
-        let (mut first, mut second) = self.peek_slices(state.peek_pos as usize);
-        let written = first.read(buf).unwrap(); // Can't fail.
-        second.read_exact(&mut buf[written..])?;
+        let peek_pos = state.peek_pos as usize;
+        if let Some(offset) = self.seek_peek_offset(state.peek_pos) {
+            self.lookahead_end(peek_pos, buf.len(), false)
+                .and_then(|_| self.read_past_buffer(offset, buf))
+                .and_then(|read| {
+                    if read < buf.len() {
+                        return Err(Error::new(
+                            ErrorKind::UnexpectedEof,
+                            "failed to fill whole buffer",
+                        ));
+                    }
+                    Ok(())
+                })
+                .map_err(|e| self.peek_error(state.peek_pos, e))?;
+            state.peek_pos += buf.len() as u64;
+            return Ok(());
+        }
+
+        self.lookahead_end(peek_pos, buf.len(), false)
+            .and_then(|end| self.request_buffer(end))
+            .and_then(|_| self.peek_slice(peek_pos).read_exact(buf))
+            .map_err(|e| self.peek_error(state.peek_pos, e))?;
         state.peek_pos += buf.len() as u64;
         Ok(())
     }
@@ -157,56 +1258,169 @@ impl<R: Read> PeekReadImpl for BufPeekReader<R> {
         match pos {
             SeekFrom::Start(offset) => state.peek_pos = offset,
             SeekFrom::Current(offset) => {
-                state.peek_pos = seek_add_offset(state.peek_pos, offset)?;
+                state.peek_pos = seek_add_offset(state.peek_pos, offset)
+                    .map_err(|e| label_error(&self.label, e))?;
+            }
+            SeekFrom::End(offset) if self.seek_fn.is_some() => {
+                // Find the end by seeking rather than buffering the stream.
+                let seek = self.seek_fn.unwrap();
+                let inner_start = seek(&mut self.inner, SeekFrom::Current(0))?;
+                let inner_end = seek(&mut self.inner, SeekFrom::End(0))?;
+                seek(&mut self.inner, SeekFrom::Start(inner_start))?;
+                let end = self.buf_len() as u64 + inner_end.saturating_sub(inner_start);
+                self.lookahead_end(0, end.try_into().unwrap_or(usize::MAX), false)?;
+                state.peek_pos =
+                    seek_add_offset(end, offset).map_err(|e| label_error(&self.label, e))?;
             }
             SeekFrom::End(offset) => {
-                let mut requested_buffer_size = self.buf_storage.len();
-                while self.buf_storage.len() == requested_buffer_size {
-                    requested_buffer_size = (requested_buffer_size * 2).max(Self::MIN_READ_TO_END);
+                // Finding the end may read one byte past the lookahead limit
+                // to tell whether the stream ends there.
+                let max_buffer_size = self.lookahead_limit.map_or(usize::MAX, |l| l + 1);
+                let mut requested_buffer_size = self.buf_len();
+                while self.buf_len() == requested_buffer_size {
+                    if self.buf_len() >= max_buffer_size {
+                        self.lookahead_end(0, self.buf_len(), false)?;
+                    }
+                    requested_buffer_size = (requested_buffer_size * 2)
+                        .max(Self::MIN_READ_TO_END)
+                        .min(max_buffer_size);
                     self.request_buffer(requested_buffer_size)?;
                 }
-                state.peek_pos = seek_add_offset(self.buf_storage.len() as u64, offset)?;
+                state.peek_pos = seek_add_offset(self.buf_len() as u64, offset)
+                    .map_err(|e| label_error(&self.label, e))?;
             }
         }
         Ok(state.peek_pos)
     }
+
+    fn peek_commit(&mut self, state: &mut PeekCursorState) -> Result<()> {
+        if let Some(offset) = self.seek_peek_offset(state.peek_pos) {
+            // Skip over the data past the buffer by seeking.
+            let seek = self.seek_fn.unwrap();
+            let start = seek(&mut self.inner, SeekFrom::Current(0))?;
+            seek(
+                &mut self.inner,
+                SeekFrom::Start(start.saturating_add(offset)),
+            )?;
+            self.inner_pos += offset;
+            self.consume(self.buf_len());
+            state.peek_pos = 0;
+            return Ok(());
+        }
+
+        // We may have seeked past the buffered data.
+        self.request_buffer(state.peek_pos as usize)?;
+        self.consume(state.peek_pos as usize);
+        state.peek_pos = 0;
+        Ok(())
+    }
 }
 
 impl<R: Read> Read for BufPeekReader<R> {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
-        let (mut first, mut second) = self.buf_storage.as_slices();
-        let mut written = first.read(buf).unwrap(); // Can't fail.
-        written += second.read(&mut buf[written..]).unwrap(); // Can't fail.
+        if self.buf_len() == 0 {
+            // Nothing buffered, read straight into buf.
+            let written = self.inner.read(buf)?;
+            self.count_pulled(written as u64);
+            return Ok(written);
+        }
+
+        let written = self.buffer().read(buf).unwrap(); // Can't fail.
         self.inner.read(&mut buf[written..]).map(|inner_written| {
             self.consume(written);
+            self.count_pulled(inner_written as u64);
             written + inner_written
         })
     }
 
     fn read_exact(&mut self, buf: &mut [u8]) -> Result<()> {
-        let (mut first, mut second) = self.buf_storage.as_slices();
-        let mut written = first.read(buf).unwrap(); // Can't fail.
-        written += second.read(&mut buf[written..])?; // Can't fail.
-        self.inner
-            .read_exact(&mut buf[written..])
-            .map(|_| self.consume(buf.len()))
+        if self.buf_len() == 0 {
+            // Nothing buffered, read straight into buf.
+            self.inner.read_exact(buf)?;
+            self.count_pulled(buf.len() as u64);
+            return Ok(());
+        }
+
+        let written = self.buffer().read(buf).unwrap(); // Can't fail.
+        self.inner.read_exact(&mut buf[written..]).map(|_| {
+            self.count_pulled((buf.len() - written) as u64);
+            self.consume(buf.len())
+        })
+    }
+
+    #[cfg(feature = "read_buf")]
+    fn read_buf(&mut self, mut cursor: BorrowedCursor<'_>) -> Result<()> {
+        if self.buf_len() == 0 {
+            let capacity = cursor.capacity();
+            self.inner.read_buf(cursor.reborrow())?;
+            self.count_pulled((capacity - cursor.capacity()) as u64);
+            return Ok(());
+        }
+
+        let written = self.buf_len().min(cursor.capacity());
+        cursor.append(&self.buffer()[..written]);
+        self.consume(written);
+        Ok(())
     }
 }
 
 impl<R: Read> BufRead for BufPeekReader<R> {
     fn fill_buf(&mut self) -> Result<&[u8]> {
-        self.request_buffer(self.min_read_size)?;
-        let (first, second) = self.buf_storage.as_slices();
-        if !first.is_empty() {
-            Ok(first)
-        } else {
-            Ok(second)
-        }
+        let max_peek_size = self.max_peek_size.unwrap_or(usize::MAX);
+        let nbytes = self.min_read_size.max(1).min(max_peek_size);
+        self.request_buffer_nonblocking(nbytes, 0)?;
+        Ok(self.buffer())
     }
 
     fn consume(&mut self, amt: usize) {
-        for _ in 0..amt.min(self.buf_storage.len()) {
-            self.buf_storage.pop_front();
+        self.buf_begin += amt.min(self.buf_len());
+        if self.buf_begin == self.buf_storage.len() {
+            self.buf_storage.clear();
+            self.buf_begin = 0;
         }
+        self.unread_len = self.unread_len.saturating_sub(amt);
+    }
+
+    fn read_until(&mut self, delim: u8, buf: &mut Vec<u8>) -> Result<usize> {
+        // Scan the buffered data directly, and refill it with single reads of
+        // a decent size rather than the minimum read size, without waiting
+        // for more data than the stream has available.
+        let mut read = 0;
+        loop {
+            let buffer = self.buffer();
+            if let Some(i) = buffer.iter().position(|&b| b == delim) {
+                buf.extend_from_slice(&buffer[..=i]);
+                self.consume(i + 1);
+                return Ok(read + i + 1);
+            }
+            let n = buffer.len();
+            buf.extend_from_slice(buffer);
+            self.consume(n);
+            read += n;
+
+            let mut chunk_size = Self::READ_UNTIL_CHUNK_SIZE.max(self.min_read_size);
+            if let Some(max_read_size) = self.max_read_size {
+                chunk_size = chunk_size.min(max_read_size);
+            }
+            if let Some(max_peek_size) = self.max_peek_size {
+                chunk_size = chunk_size.min(max_peek_size);
+            }
+            match self.read_once(chunk_size.max(1)) {
+                Ok(0) => return Ok(read),
+                Ok(_) => {}
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn read_line(&mut self, buf: &mut String) -> Result<usize> {
+        let mut bytes = Vec::new();
+        let read = self.read_until(b'\n', &mut bytes)?;
+        let line = String::from_utf8(bytes).map_err(|_| {
+            Error::new(ErrorKind::InvalidData, "stream did not contain valid UTF-8")
+        })?;
+        buf.push_str(&line);
+        Ok(read)
     }
 }
@@ -0,0 +1,148 @@
+use std::io::{BufRead, Read, Result, Seek, SeekFrom};
+
+use crate::{
+    detail::{PeekCursorState, PeekReadImpl},
+    PeekCursor, PeekRead,
+};
+
+/// A wrapper for a [`PeekRead`] stream that checks that peeking doesn't
+/// affect the stream, to catch bugs in [`PeekRead`] implementations.
+///
+/// When a peek cursor is created the next few bytes after the read cursor are
+/// recorded, and when it is dropped they are peeked again and compared,
+/// panicking if they changed. Committing a peek records them anew.
+///
+/// The checks are only done in builds with debug assertions, in other builds
+/// [`PeekRead::peek`] hands out the peek cursor of the underlying stream
+/// directly, so this can be left in place outside of tests.
+///
+/// ```
+/// # use std::io::{Cursor, Read, Seek, SeekFrom};
+/// use peekread::{CheckedPeekReader, PeekRead, SeekPeekReader};
+/// let mut f = CheckedPeekReader::new(SeekPeekReader::new(Cursor::new("hello")));
+/// let mut peeker = f.peek();
+/// peeker.seek(SeekFrom::End(-2))?;
+/// peeker.read_exact(&mut [0; 2])?;
+/// drop(peeker);
+/// assert!(f.starts_with("hel")?);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct CheckedPeekReader<R> {
+    inner: R,
+    // The bytes after the read cursor when the peek started, if they could
+    // be peeked.
+    snapshot: Option<Vec<u8>>,
+}
+
+impl<R: PeekRead> CheckedPeekReader<R> {
+    // How many bytes after the read cursor are compared.
+    const SNAPSHOT_LEN: usize = 16;
+
+    /// Creates a new [`CheckedPeekReader`].
+    pub fn new(reader: R) -> Self {
+        Self {
+            inner: reader,
+            snapshot: None,
+        }
+    }
+
+    /// Gets a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Gets a mutable reference to the underlying reader.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Unwraps this `CheckedPeekReader<R>`, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn take_snapshot(&mut self) -> Option<Vec<u8>> {
+        let mut snapshot = vec![0; Self::SNAPSHOT_LEN];
+        let len = self.inner.peek_at(0, &mut snapshot).ok()?;
+        snapshot.truncate(len);
+        Some(snapshot)
+    }
+}
+
+impl<R: PeekRead> Read for CheckedPeekReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        self.inner.read(buf)
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<()> {
+        self.inner.read_exact(buf)
+    }
+}
+
+impl<R: PeekRead> PeekRead for CheckedPeekReader<R> {
+    #[cfg(debug_assertions)]
+    fn peek(&mut self) -> PeekCursor<'_> {
+        self.snapshot = self.take_snapshot();
+        PeekCursor::new(self)
+    }
+
+    #[cfg(not(debug_assertions))]
+    fn peek(&mut self) -> PeekCursor<'_> {
+        self.inner.peek()
+    }
+
+    fn buffered_lookahead(&self) -> usize {
+        self.inner.buffered_lookahead()
+    }
+}
+
+impl<R: PeekRead> PeekReadImpl for CheckedPeekReader<R> {
+    fn peek_seek(&mut self, state: &mut PeekCursorState, pos: SeekFrom) -> Result<u64> {
+        let mut peeker = self.inner.peek();
+        peeker.seek(SeekFrom::Start(state.peek_pos))?;
+        state.peek_pos = peeker.seek(pos)?;
+        Ok(state.peek_pos)
+    }
+
+    fn peek_read(&mut self, state: &mut PeekCursorState, buf: &mut [u8]) -> Result<usize> {
+        let mut peeker = self.inner.peek();
+        peeker.seek(SeekFrom::Start(state.peek_pos))?;
+        let written = peeker.read(buf)?;
+        state.peek_pos += written as u64;
+        Ok(written)
+    }
+
+    fn peek_fill_buf<'a>(&'a mut self, state: &'a mut PeekCursorState) -> Result<&'a [u8]> {
+        let mut peeker = self.inner.peek();
+        peeker.seek(SeekFrom::Start(state.peek_pos))?;
+        state.buf.clear();
+        state.buf.extend_from_slice(peeker.fill_buf()?);
+        Ok(&state.buf)
+    }
+
+    fn peek_consume(&mut self, state: &mut PeekCursorState, amt: usize) {
+        state.peek_pos += amt as u64;
+    }
+
+    fn peek_commit(&mut self, state: &mut PeekCursorState) -> Result<()> {
+        let mut peeker = self.inner.peek();
+        peeker.seek(SeekFrom::Start(state.peek_pos))?;
+        peeker.commit()?;
+        state.peek_pos = 0;
+        // The read cursor moved on purpose.
+        self.snapshot = self.take_snapshot();
+        Ok(())
+    }
+
+    fn peek_drop(&mut self, _state: &mut PeekCursorState) {
+        let before = self.snapshot.take();
+        // Don't turn a panic while peeking into an abort.
+        if std::thread::panicking() {
+            return;
+        }
+        if let (Some(before), Some(after)) = (before, self.take_snapshot()) {
+            assert_eq!(before, after, "peeking moved the read cursor");
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use std::fmt::{Debug, Error as FmtError, Formatter};
+use std::io::{Error, Result, SeekFrom};
+use std::pin::Pin;
+#[cfg(feature = "futures-io")]
+use std::task::ready;
+use std::task::{Context, Poll};
+
+use tokio::io::{AsyncBufRead, AsyncRead, AsyncSeek, ReadBuf};
+
+type FmtResult = std::result::Result<(), FmtError>;
+
+use crate::detail::{AsyncPeekReadImpl, PeekCursorState};
+#[cfg(doc)]
+use crate::{AsyncPeekRead, PeekCursor};
+
+/// An object implementing [`AsyncRead`], [`AsyncBufRead`] and [`AsyncSeek`]
+/// to peek ahead in an asynchronous stream without affecting the original
+/// stream. The asynchronous counterpart of [`PeekCursor`].
+pub struct AsyncPeekCursor<'a> {
+    inner: &'a mut dyn AsyncPeekReadImpl,
+    state: PeekCursorState,
+    pending_seek: Option<SeekFrom>,
+}
+
+impl<'a> AsyncPeekCursor<'a> {
+    /// Creates a new [`AsyncPeekCursor`].
+    ///
+    /// Unless you are trying to implement [`AsyncPeekRead`] you will never
+    /// call this, you should look at [`AsyncPeekRead::peek`] instead.
+    pub fn new(inner: &'a mut dyn AsyncPeekReadImpl) -> Self {
+        Self {
+            inner,
+            state: PeekCursorState::new(),
+            pending_seek: None,
+        }
+    }
+}
+
+impl<'a> AsyncRead for AsyncPeekCursor<'a> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<()>> {
+        let this = self.get_mut();
+        this.inner.poll_peek_read(cx, &mut this.state, buf)
+    }
+}
+
+impl<'a> AsyncBufRead for AsyncPeekCursor<'a> {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<&[u8]>> {
+        let this = self.get_mut();
+        this.inner.poll_peek_fill_buf(cx, &mut this.state)
+    }
+
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        let this = self.get_mut();
+        this.inner.peek_consume(&mut this.state, amt)
+    }
+}
+
+impl<'a> AsyncSeek for AsyncPeekCursor<'a> {
+    fn start_seek(self: Pin<&mut Self>, position: SeekFrom) -> Result<()> {
+        let this = self.get_mut();
+        if this.pending_seek.is_some() {
+            return Err(Error::other("other seek operation is in progress"));
+        }
+        this.pending_seek = Some(position);
+        Ok(())
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<u64>> {
+        let this = self.get_mut();
+        match this.pending_seek {
+            Some(pos) => {
+                let result = this.inner.poll_peek_seek(cx, &mut this.state, pos);
+                if result.is_ready() {
+                    this.pending_seek = None;
+                }
+                result
+            }
+            None => Poll::Ready(Ok(this.state.peek_pos)),
+        }
+    }
+}
+
+impl<'a> Debug for AsyncPeekCursor<'a> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("AsyncPeekCursor")
+            .field("inner", &format_args!("<dyn AsyncPeekReadImpl>"))
+            .field("peek_pos", &self.state.peek_pos)
+            .field("buf_len", &self.state.buf.len())
+            .finish()
+    }
+}
+
+#[cfg(feature = "futures-io")]
+impl<'a> futures_io::AsyncRead for AsyncPeekCursor<'a> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize>> {
+        let mut buf = ReadBuf::new(buf);
+        ready!(AsyncRead::poll_read(self, cx, &mut buf))?;
+        Poll::Ready(Ok(buf.filled().len()))
+    }
+}
+
+#[cfg(feature = "futures-io")]
+impl<'a> futures_io::AsyncBufRead for AsyncPeekCursor<'a> {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<&[u8]>> {
+        AsyncBufRead::poll_fill_buf(self, cx)
+    }
+
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        AsyncBufRead::consume(self, amt)
+    }
+}
+
+#[cfg(feature = "futures-io")]
+impl<'a> futures_io::AsyncSeek for AsyncPeekCursor<'a> {
+    fn poll_seek(self: Pin<&mut Self>, cx: &mut Context<'_>, pos: SeekFrom) -> Poll<Result<u64>> {
+        // Pending doesn't change the peek position, so this can simply be
+        // polled again.
+        let this = self.get_mut();
+        this.inner.poll_peek_seek(cx, &mut this.state, pos)
+    }
+}
@@ -1,25 +1,59 @@
 use std::fmt::{Debug, Error as FmtError, Formatter};
-use std::io::{BufRead, Read, Result, Seek, SeekFrom};
+#[cfg(feature = "read_buf")]
+use std::io::BorrowedCursor;
+use std::io::{BufRead, Read, Result, Seek, SeekFrom, Take};
 
 type FmtResult = std::result::Result<(), FmtError>;
 
 use crate::detail::PeekReadImpl;
 #[cfg(doc)]
-use crate::PeekRead;
+use crate::{BufPeekReader, PeekRead, SeekPeekReader};
 
 /// The internal state of a [`PeekCursor`]. See [`PeekReadImpl`].
 ///
-/// All fields here are just provided to help you make your implementation
-/// possible, you may use them in any way you see fit. [`PeekCursor::new`]
-/// initializes these fields to their default value.
-#[non_exhaustive]
+/// This keeps the peek position and a scratch buffer, to help you make your
+/// implementation possible. You may use them in any way you see fit.
+/// [`PeekCursor::new`] starts out with a peek position of 0 and an empty
+/// scratch buffer.
 #[derive(Debug)]
 pub struct PeekCursorState {
-    /// The peek position in the stream.
-    pub peek_pos: u64,
+    pub(crate) peek_pos: u64,
+    pub(crate) buf: Vec<u8>,
+}
+
+impl PeekCursorState {
+    pub(crate) fn new() -> Self {
+        Self {
+            peek_pos: 0,
+            buf: Vec::new(),
+        }
+    }
+
+    /// Gets the peek position in the stream.
+    pub fn pos(&self) -> u64 {
+        self.peek_pos
+    }
+
+    /// Sets the peek position in the stream.
+    pub fn set_pos(&mut self, pos: u64) {
+        self.peek_pos = pos;
+    }
+
+    /// Returns the scratch buffer, e.g. to hold the data returned by
+    /// [`PeekReadImpl::peek_fill_buf`]. It is grown to at least `min_len`
+    /// bytes if needed and keeps its contents between calls.
+    pub fn scratch_mut(&mut self, min_len: usize) -> &mut [u8] {
+        if self.buf.len() < min_len {
+            self.buf.resize(min_len, 0);
+        }
+        &mut self.buf
+    }
+}
 
-    /// A buffer you can use for BufRead.
-    pub buf: [u8; 1],
+/// A saved position of a [`PeekCursor`], see [`PeekCursor::checkpoint`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PeekCheckpoint {
+    peek_pos: u64,
 }
 
 /// An object implementing [`BufRead`] and [`Seek`] to peek ahead in a stream
@@ -38,14 +72,139 @@ impl<'a> PeekCursor<'a> {
     pub fn new(inner: &'a mut dyn PeekReadImpl) -> Self {
         Self {
             inner,
-            state: PeekCursorState {
-                peek_pos: 0,
-                buf: [0],
-            },
+            state: PeekCursorState::new(),
         }
     }
 }
 
+impl<'a> PeekCursor<'a> {
+    /// Advances the read cursor of the stream to the position of this peek
+    /// cursor, consuming everything that was peeked over.
+    ///
+    /// This is cheaper than reading the data again, e.g. for
+    /// [`BufPeekReader`] it simply drops the data from the buffer, and for
+    /// [`SeekPeekReader`] the stream is left where the peek cursor is instead
+    /// of being restored to the read cursor when dropped.
+    pub fn commit(mut self) -> Result<()> {
+        self.inner.peek_commit(&mut self.state)
+    }
+
+    /// Like [`Self::commit`], but keeps the peek cursor alive. The read
+    /// cursor is advanced to the peek position, and peeking continues from
+    /// there with a peek position of 0.
+    ///
+    /// ```
+    /// # use std::io::{Read, Seek, SeekFrom};
+    /// use peekread::{BufPeekReader, PeekRead};
+    /// let mut f = BufPeekReader::new(&b"header;body"[..]);
+    /// let mut peeker = f.peek();
+    /// peeker.seek(SeekFrom::Start(7))?;
+    /// peeker.commit_here()?;
+    /// assert_eq!(peeker.stream_position()?, 0);
+    /// let mut body = [0; 4];
+    /// peeker.read_exact(&mut body)?;
+    /// assert_eq!(&body, b"body");
+    /// drop(peeker);
+    /// assert!(f.starts_with("body")?);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn commit_here(&mut self) -> Result<()> {
+        self.inner.peek_commit(&mut self.state)
+    }
+
+    /// Ends the peek, restoring the stream to how it was before peeking and
+    /// reporting any error doing so.
+    ///
+    /// Dropping a [`PeekCursor`] does the same, but has no way to report
+    /// errors. E.g. for [`SeekPeekReader`] the stream is seeked back to the
+    /// position the read cursor had when [`PeekRead::peek`] was called, and if
+    /// that seek fails dropping leaves the stream at an unexpected position
+    /// without any signal. If this returns an error the restore is attempted
+    /// once more when the cursor is dropped.
+    pub fn finish(mut self) -> Result<()> {
+        self.inner.peek_finish(&mut self.state)
+    }
+
+    /// Like [`BufRead::fill_buf`], but the returned data is at least `n` bytes
+    /// long, unless the stream ends first. Use this to get a header of a
+    /// known size in one piece. Like [`BufRead::fill_buf`] this doesn't move
+    /// the peek cursor.
+    ///
+    /// ```
+    /// # use std::io::BufRead;
+    /// use peekread::{BufPeekReader, PeekRead};
+    /// let mut f = BufPeekReader::new(&b"\x00\x05hello, world"[..]);
+    /// f.set_max_read_size(Some(1));
+    /// let mut peeker = f.peek();
+    /// let header = peeker.fill_buf_at_least(2)?;
+    /// let len = u16::from_be_bytes([header[0], header[1]]) as usize;
+    /// peeker.consume(2);
+    /// assert_eq!(&peeker.fill_buf_at_least(len)?[..len], b"hello");
+    /// assert_eq!(peeker.fill_buf_at_least(100)?.len(), 12);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn fill_buf_at_least(&mut self, n: usize) -> Result<&[u8]> {
+        self.inner.peek_fill_buf_at_least(&mut self.state, n)
+    }
+
+    /// Returns a view of at most the next `len` bytes from the peek
+    /// position, e.g. to hand a length-prefixed field to a parser that reads
+    /// until the end. Reading from the view moves the peek cursor along.
+    ///
+    /// ```
+    /// # use std::io::Read;
+    /// use peekread::PeekRead;
+    /// let mut f = &b"\x03abcdef"[..];
+    /// let mut peeker = f.peek();
+    /// let mut len = [0];
+    /// peeker.read_exact(&mut len)?;
+    /// let mut field = Vec::new();
+    /// peeker.window(len[0] as u64).read_to_end(&mut field)?;
+    /// assert_eq!(field, b"abc");
+    /// let mut rest = Vec::new();
+    /// peeker.read_to_end(&mut rest)?;
+    /// assert_eq!(rest, b"def");
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn window(&mut self, len: u64) -> Take<&mut Self> {
+        Read::take(self, len)
+    }
+
+    /// Saves the current peek position so it can be returned to later with
+    /// [`Self::restore`], e.g. to back out of a speculative parse.
+    ///
+    /// ```
+    /// # use std::io::Read;
+    /// use peekread::{BufPeekReader, PeekRead};
+    /// let mut f = BufPeekReader::new(&b"12ab"[..]);
+    /// let mut peeker = f.peek();
+    /// let cp = peeker.checkpoint();
+    /// peeker.read_exact(&mut [0; 3])?;
+    /// peeker.restore(cp)?;
+    /// let mut s = String::new();
+    /// peeker.read_to_string(&mut s)?;
+    /// assert_eq!(s, "12ab");
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn checkpoint(&self) -> PeekCheckpoint {
+        PeekCheckpoint {
+            peek_pos: self.state.peek_pos,
+        }
+    }
+
+    /// Moves the peek cursor back (or forward) to a position saved with
+    /// [`Self::checkpoint`].
+    ///
+    /// Only checkpoints taken from this cursor are meaningful. A checkpoint
+    /// always refers to a position at or after the read cursor, so restoring
+    /// it never moves the peek cursor behind the read cursor.
+    pub fn restore(&mut self, checkpoint: PeekCheckpoint) -> Result<()> {
+        self.inner
+            .peek_seek(&mut self.state, SeekFrom::Start(checkpoint.peek_pos))?;
+        Ok(())
+    }
+}
+
 impl<'a> Seek for PeekCursor<'a> {
     fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
         self.inner.peek_seek(&mut self.state, pos)
@@ -65,6 +224,11 @@ impl<'a> Read for PeekCursor<'a> {
         self.inner.peek_read_exact(&mut self.state, buf)
     }
 
+    #[cfg(feature = "read_buf")]
+    fn read_buf(&mut self, cursor: BorrowedCursor<'_>) -> Result<()> {
+        self.inner.peek_read_buf(&mut self.state, cursor)
+    }
+
     fn read_to_end(&mut self, buf: &mut Vec<u8>) -> Result<usize> {
         self.inner.peek_read_to_end(&mut self.state, buf)
     }
@@ -92,8 +256,12 @@ impl<'a> Drop for PeekCursor<'a> {
 
 impl<'a> Debug for PeekCursor<'a> {
     fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        // We can't print the inner stream, and the scratch buffer contents
+        // are meaningless on their own.
         f.debug_struct("PeekCursor")
-            .field("state", &self.state)
+            .field("inner", &format_args!("<dyn PeekReadImpl>"))
+            .field("peek_pos", &self.state.peek_pos)
+            .field("buf_len", &self.state.buf.len())
             .finish()
     }
 }
@@ -1,12 +1,25 @@
+#[cfg(feature = "tokio")]
+pub(crate) mod async_cursor;
 pub(crate) mod cursor;
 
+use crate::util::read_fully;
+pub use crate::util::seek_add_offset;
 #[cfg(doc)]
 use crate::{PeekCursor, PeekRead};
-use cursor::DefaultImplPeekCursor;
+pub(crate) use cursor::DefaultImplPeekCursor;
 pub use cursor::PeekCursorState;
+#[cfg(feature = "read_buf")]
+use std::io::BorrowedCursor;
 #[cfg(doc)]
 use std::io::BufRead;
-use std::io::{Read, Result, Seek, SeekFrom};
+use std::io::{Error, Read, Result, Seek, SeekFrom};
+#[cfg(feature = "tokio")]
+use std::task::{Context, Poll};
+
+#[cfg(all(doc, feature = "tokio"))]
+use crate::{AsyncPeekCursor, AsyncPeekRead};
+#[cfg(feature = "tokio")]
+use tokio::io::ReadBuf;
 
 /// A helper trait used to implement [`PeekRead`].
 ///
@@ -19,6 +32,12 @@ use std::io::{Read, Result, Seek, SeekFrom};
 /// there is some storage available to aid you in case the object you're
 /// implementing [`PeekReadImpl`] on does not have the needed storage available
 /// to keep the cursor state (e.g. the [`PeekRead`] implementation for `&[u8]`).
+///
+/// Use [`seek_add_offset`] to compute relative positions in
+/// [`Self::peek_seek`].
+// The explicit lifetimes are part of the signatures implementors may spell
+// out, removing them would break those implementations.
+#[allow(clippy::needless_lifetimes)]
 pub trait PeekReadImpl {
     /// Used to implement `self.peek().seek(pos)`. See [`Seek::seek`].
     fn peek_seek<'a>(&'a mut self, state: &'a mut PeekCursorState, pos: SeekFrom) -> Result<u64>;
@@ -43,6 +62,54 @@ pub trait PeekReadImpl {
         DefaultImplPeekCursor::new(self, state).stream_position()
     }
 
+    /// Used to implement `self.peek().fill_buf_at_least(n)`. See
+    /// [`PeekCursor::fill_buf_at_least`].
+    ///
+    /// By default this copies what [`Self::peek_fill_buf`] gives into the
+    /// scratch buffer of `state`, and if that's too few bytes reads the rest
+    /// after it and seeks back.
+    fn peek_fill_buf_at_least<'a>(
+        &'a mut self,
+        state: &'a mut PeekCursorState,
+        n: usize,
+    ) -> Result<&'a [u8]> {
+        let peek_pos = state.peek_pos;
+        let mut buf = std::mem::take(&mut state.buf);
+        buf.clear();
+        let filled = match self.peek_fill_buf(state) {
+            Ok(chunk) => {
+                let filled = chunk.len().min(n);
+                buf.extend_from_slice(&chunk[..filled]);
+                filled
+            }
+            Err(e) => {
+                state.buf = buf;
+                return Err(e);
+            }
+        };
+
+        let mut result = Ok(0);
+        if filled > 0 && filled < n {
+            self.peek_consume(state, filled);
+            buf.resize(n, 0);
+            let mut peeker = DefaultImplPeekCursor::new(self, state);
+            result = read_fully(&mut peeker, &mut buf[filled..]);
+            let seeked = peeker.seek(SeekFrom::Start(peek_pos));
+            result = seeked.and(result);
+        }
+        state.buf = buf;
+        match result {
+            Ok(read) => {
+                state.buf.truncate(filled + read);
+                Ok(&state.buf)
+            }
+            Err(e) => {
+                state.buf.clear();
+                Err(e)
+            }
+        }
+    }
+
     /// Used to implement `self.peek().read_exact(buf)`. See
     /// [`Read::read_exact`].
     fn peek_read_exact<'a, 'b>(
@@ -53,6 +120,18 @@ pub trait PeekReadImpl {
         DefaultImplPeekCursor::new(self, state).read_exact(buf)
     }
 
+    /// Used to implement `self.peek().read_buf(cursor)`. See
+    /// [`Read::read_buf`]. Override this to fill `cursor` without
+    /// initializing it first.
+    #[cfg(feature = "read_buf")]
+    fn peek_read_buf<'a>(
+        &'a mut self,
+        state: &'a mut PeekCursorState,
+        cursor: BorrowedCursor<'_>,
+    ) -> Result<()> {
+        DefaultImplPeekCursor::new(self, state).read_buf(cursor)
+    }
+
     /// Used to implement `self.peek().read_to_end(buf)`. See
     /// [`Read::read_to_end`].
     fn peek_read_to_end<'a, 'b>(
@@ -73,8 +152,69 @@ pub trait PeekReadImpl {
         DefaultImplPeekCursor::new(self, state).read_to_string(buf)
     }
 
+    /// Used to implement `self.peek().commit()` and
+    /// [`PeekCursor::commit_here`]. See [`PeekCursor::commit`].
+    ///
+    /// Should advance the read cursor to the peek position, after which the
+    /// peek position is 0. The cursor may keep peeking afterwards, see
+    /// [`PeekCursor::commit_here`], so this must leave the stream ready for
+    /// that. By default this is not supported and returns an error.
+    fn peek_commit<'a>(&'a mut self, _state: &'a mut PeekCursorState) -> Result<()> {
+        Err(Error::other(
+            "committing a peek is not supported for this stream",
+        ))
+    }
+
+    /// Used to implement `self.peek().finish()`. See [`PeekCursor::finish`].
+    ///
+    /// Should do the cleanup that [`Self::peek_drop`] would do, but report
+    /// errors instead of ignoring them. [`Self::peek_drop`] is still called
+    /// afterwards, so it must not undo anything twice. Does nothing by
+    /// default.
+    fn peek_finish<'a>(&'a mut self, _state: &'a mut PeekCursorState) -> Result<()> {
+        Ok(())
+    }
+
     /// Called when the `PeekCursor` is dropped.
     fn peek_drop<'a>(&'a mut self, _state: &'a mut PeekCursorState) {
         // Do nothing by default.
     }
 }
+
+/// A helper trait used to implement [`AsyncPeekRead`], the asynchronous
+/// counterpart of [`PeekReadImpl`].
+///
+/// Implement this trait on your type and then implement
+/// [`AsyncPeekRead::peek`] returning an [`AsyncPeekCursor`]. The methods
+/// follow the conventions of their [`tokio::io`] counterparts: when they
+/// return [`Poll::Pending`] they must have scheduled a wakeup through `cx` and
+/// must not have changed the peek position.
+#[cfg(feature = "tokio")]
+pub trait AsyncPeekReadImpl {
+    /// Used to implement `AsyncRead::poll_read` for the peek cursor.
+    fn poll_peek_read(
+        &mut self,
+        cx: &mut Context<'_>,
+        state: &mut PeekCursorState,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<()>>;
+
+    /// Used to implement `AsyncBufRead::poll_fill_buf` for the peek cursor.
+    fn poll_peek_fill_buf<'a>(
+        &'a mut self,
+        cx: &mut Context<'_>,
+        state: &'a mut PeekCursorState,
+    ) -> Poll<Result<&'a [u8]>>;
+
+    /// Used to implement `AsyncBufRead::consume` for the peek cursor.
+    fn peek_consume(&mut self, state: &mut PeekCursorState, amt: usize);
+
+    /// Used to implement `AsyncSeek` for the peek cursor. Returns the new
+    /// peek position.
+    fn poll_peek_seek(
+        &mut self,
+        cx: &mut Context<'_>,
+        state: &mut PeekCursorState,
+        pos: SeekFrom,
+    ) -> Poll<Result<u64>>;
+}
@@ -0,0 +1,181 @@
+use std::io::{Cursor, Error, ErrorKind, Read, Result};
+
+use crate::PeekRead;
+
+/// A byte order mark at the start of a text stream, see
+/// [`PeekReadExt::detect_bom`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Bom {
+    /// UTF-8, `EF BB BF`.
+    Utf8,
+    /// UTF-16 little-endian, `FF FE`.
+    Utf16Le,
+    /// UTF-16 big-endian, `FE FF`.
+    Utf16Be,
+    /// UTF-32 little-endian, `FF FE 00 00`.
+    Utf32Le,
+    /// UTF-32 big-endian, `00 00 FE FF`.
+    Utf32Be,
+}
+
+impl Bom {
+    // Longer marks come first, UTF-16LE is a prefix of UTF-32LE.
+    const ALL: [Bom; 5] = [
+        Bom::Utf32Le,
+        Bom::Utf32Be,
+        Bom::Utf8,
+        Bom::Utf16Le,
+        Bom::Utf16Be,
+    ];
+
+    /// The bytes of this byte order mark.
+    pub fn as_bytes(self) -> &'static [u8] {
+        match self {
+            Bom::Utf8 => b"\xEF\xBB\xBF",
+            Bom::Utf16Le => b"\xFF\xFE",
+            Bom::Utf16Be => b"\xFE\xFF",
+            Bom::Utf32Le => b"\xFF\xFE\x00\x00",
+            Bom::Utf32Be => b"\x00\x00\xFE\xFF",
+        }
+    }
+}
+
+/// Higher-level peeking combinators, implemented for every [`PeekRead`]
+/// stream.
+///
+/// None of the peeking methods advance the stream. They fail with
+/// [`ErrorKind::UnexpectedEof`] if the stream ends before enough bytes could
+/// be peeked.
+///
+/// ```
+/// # use std::io::{Cursor, ErrorKind};
+/// use peekread::{PeekReadExt, SeekPeekReader};
+/// let mut f = SeekPeekReader::new(Cursor::new(b"\x01\x02\x03\x04\x05\x06\x07\x08"));
+/// assert_eq!(f.peek_array::<3>()?, [1, 2, 3]);
+/// assert_eq!(f.peek_u8()?, 1);
+/// assert_eq!(f.peek_u16_le()?, 0x0201);
+/// assert_eq!(f.peek_u16_be()?, 0x0102);
+/// assert_eq!(f.peek_u32_le()?, 0x04030201);
+/// assert_eq!(f.peek_u32_be()?, 0x01020304);
+/// assert_eq!(f.peek_u64_le()?, 0x0807060504030201);
+/// assert_eq!(f.peek_u64_be()?, 0x0102030405060708);
+/// assert_eq!(f.peek_array::<9>().unwrap_err().kind(), ErrorKind::UnexpectedEof);
+///
+/// f.expect_prefix(b"\x01\x02")?;
+/// assert_eq!(f.expect_prefix(b"\x01").unwrap_err().kind(), ErrorKind::InvalidData);
+/// assert_eq!(f.peek_u8()?, 3);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub trait PeekReadExt: PeekRead {
+    /// Peeks the next `N` bytes of the stream.
+    fn peek_array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let mut buf = [0; N];
+        self.peek().read_exact(&mut buf)?;
+        Ok(buf)
+    }
+
+    /// Peeks the next byte of the stream.
+    fn peek_u8(&mut self) -> Result<u8> {
+        Ok(self.peek_array::<1>()?[0])
+    }
+
+    /// Peeks a little-endian `u16`.
+    fn peek_u16_le(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.peek_array()?))
+    }
+
+    /// Peeks a big-endian `u16`.
+    fn peek_u16_be(&mut self) -> Result<u16> {
+        Ok(u16::from_be_bytes(self.peek_array()?))
+    }
+
+    /// Peeks a little-endian `u32`.
+    fn peek_u32_le(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.peek_array()?))
+    }
+
+    /// Peeks a big-endian `u32`.
+    fn peek_u32_be(&mut self) -> Result<u32> {
+        Ok(u32::from_be_bytes(self.peek_array()?))
+    }
+
+    /// Peeks a little-endian `u64`.
+    fn peek_u64_le(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.peek_array()?))
+    }
+
+    /// Peeks a big-endian `u64`.
+    fn peek_u64_be(&mut self) -> Result<u64> {
+        Ok(u64::from_be_bytes(self.peek_array()?))
+    }
+
+    /// Like [`PeekRead::consume_prefix`], but returns an
+    /// [`ErrorKind::InvalidData`] error if the stream doesn't start with
+    /// `bytes`, in which case nothing is consumed.
+    fn expect_prefix<B: AsRef<[u8]>>(&mut self, bytes: B) -> Result<()> {
+        if self.consume_prefix(bytes)? {
+            Ok(())
+        } else {
+            Err(Error::new(
+                ErrorKind::InvalidData,
+                "stream did not start with the expected prefix",
+            ))
+        }
+    }
+
+    /// Copies up to the next `n` bytes of the stream into an owned
+    /// [`Cursor`], without advancing the stream. Fewer bytes are copied if the
+    /// stream ends first.
+    ///
+    /// The snapshot doesn't borrow the stream, and can itself be peeked into
+    /// since [`Cursor`] implements [`PeekRead`].
+    fn peek_snapshot(&mut self, n: usize) -> Result<Cursor<Vec<u8>>> {
+        let mut buf = Vec::new();
+        self.peek_read_to_limit(n as u64, &mut buf)?;
+        Ok(Cursor::new(buf))
+    }
+
+    /// Appends up to the next `limit` bytes of the stream to `buf` without
+    /// advancing the stream, stopping early if the stream ends. Returns the
+    /// number of bytes appended.
+    ///
+    /// Unlike [`Read::read_to_end`] on a peek cursor this can't run away on an
+    /// endless stream.
+    fn peek_read_to_limit(&mut self, limit: u64, buf: &mut Vec<u8>) -> Result<usize> {
+        self.peek().take(limit).read_to_end(buf)
+    }
+
+    /// Detects a byte order mark at the start of the stream without advancing
+    /// the stream.
+    ///
+    /// Note that a UTF-16LE stream starting with a NUL character is
+    /// indistinguishable from a UTF-32LE stream, and is detected as the latter.
+    ///
+    /// ```
+    /// use peekread::{Bom, PeekReadExt};
+    /// assert_eq!((&b"\xFF\xFEa\x00"[..]).detect_bom()?, Some(Bom::Utf16Le));
+    /// assert_eq!((&b"\xFF\xFE\x00\x00"[..]).detect_bom()?, Some(Bom::Utf32Le));
+    /// assert_eq!((&b"\xEF\xBB"[..]).detect_bom()?, None);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    fn detect_bom(&mut self) -> Result<Option<Bom>> {
+        let mut buf = [0; 4];
+        let len = self.peek_at(0, &mut buf)?;
+        Ok(Bom::ALL
+            .iter()
+            .copied()
+            .find(|bom| buf[..len].starts_with(bom.as_bytes())))
+    }
+
+    /// Like [`Self::detect_bom`], but also consumes the byte order mark if
+    /// there is one.
+    fn consume_bom(&mut self) -> Result<Option<Bom>> {
+        let bom = self.detect_bom()?;
+        if let Some(bom) = bom {
+            self.skip(bom.as_bytes().len() as u64)?;
+        }
+        Ok(bom)
+    }
+}
+
+impl<T: PeekRead + ?Sized> PeekReadExt for T {}
@@ -1,13 +1,57 @@
 use crate::detail::{PeekCursorState, PeekReadImpl};
-use crate::util::seek_add_offset;
+use crate::util::{seek_add_offset, vec_deque_slices};
+#[cfg(doc)]
+use crate::BufPeekReader;
 use crate::{PeekCursor, PeekRead};
-use std::io::{self, Cursor, Empty, Read, Result, Seek, SeekFrom, Take};
+use std::collections::VecDeque;
+use std::convert::TryInto;
+use std::io::{
+    self, BufRead, BufReader, Cursor, Empty, Error, Read, Repeat, Result, Seek, SeekFrom, Take,
+};
 
 impl<T: PeekRead + ?Sized> PeekRead for &mut T {
     #[inline]
     fn peek(&mut self) -> PeekCursor<'_> {
         (**self).peek()
     }
+
+    #[inline]
+    fn buffered_lookahead(&self) -> usize {
+        (**self).buffered_lookahead()
+    }
+
+    #[inline]
+    fn skip(&mut self, n: u64) -> Result<u64> {
+        (**self).skip(n)
+    }
+
+    #[inline]
+    fn consume_matching_prefix<B: AsRef<[u8]>>(&mut self, bytes: B) -> Result<Option<usize>> {
+        (**self).consume_matching_prefix(bytes)
+    }
+
+    #[inline]
+    fn peek_at(&mut self, offset: u64, buf: &mut [u8]) -> Result<usize> {
+        (**self).peek_at(offset, buf)
+    }
+
+    fn peek_ranges(
+        &mut self,
+        ranges: &[(u64, usize)],
+        out: &mut Vec<u8>,
+    ) -> Result<Vec<(usize, usize)>> {
+        (**self).peek_ranges(ranges, out)
+    }
+
+    #[inline]
+    fn read_prefix(&mut self, n: usize) -> Result<Vec<u8>> {
+        (**self).read_prefix(n)
+    }
+
+    #[inline]
+    fn peek_entropy_estimate(&mut self, sample: usize) -> Result<f64> {
+        (**self).peek_entropy_estimate(sample)
+    }
 }
 
 impl<T: PeekRead + ?Sized> PeekRead for Box<T> {
@@ -15,6 +59,44 @@ impl<T: PeekRead + ?Sized> PeekRead for Box<T> {
     fn peek(&mut self) -> PeekCursor<'_> {
         (**self).peek()
     }
+
+    #[inline]
+    fn buffered_lookahead(&self) -> usize {
+        (**self).buffered_lookahead()
+    }
+
+    #[inline]
+    fn skip(&mut self, n: u64) -> Result<u64> {
+        (**self).skip(n)
+    }
+
+    #[inline]
+    fn consume_matching_prefix<B: AsRef<[u8]>>(&mut self, bytes: B) -> Result<Option<usize>> {
+        (**self).consume_matching_prefix(bytes)
+    }
+
+    #[inline]
+    fn peek_at(&mut self, offset: u64, buf: &mut [u8]) -> Result<usize> {
+        (**self).peek_at(offset, buf)
+    }
+
+    fn peek_ranges(
+        &mut self,
+        ranges: &[(u64, usize)],
+        out: &mut Vec<u8>,
+    ) -> Result<Vec<(usize, usize)>> {
+        (**self).peek_ranges(ranges, out)
+    }
+
+    #[inline]
+    fn read_prefix(&mut self, n: usize) -> Result<Vec<u8>> {
+        (**self).read_prefix(n)
+    }
+
+    #[inline]
+    fn peek_entropy_estimate(&mut self, sample: usize) -> Result<f64> {
+        (**self).peek_entropy_estimate(sample)
+    }
 }
 
 impl PeekRead for Empty {
@@ -37,12 +119,71 @@ impl PeekReadImpl for Empty {
     }
 
     fn peek_consume(&mut self, _state: &mut PeekCursorState, _amt: usize) {}
+
+    fn peek_commit(&mut self, state: &mut PeekCursorState) -> Result<()> {
+        state.peek_pos = 0;
+        Ok(())
+    }
+}
+
+// How many copies of the byte fill_buf on a peek into Repeat returns.
+const REPEAT_FILL_BUF_SIZE: usize = 64;
+
+/// Peeking reads the repeated byte without advancing anything, since the
+/// stream never changes. Seeking relative to the end is an error, as there is
+/// no end.
+impl PeekRead for Repeat {
+    fn peek(&mut self) -> PeekCursor<'_> {
+        PeekCursor::new(self)
+    }
+}
+
+impl PeekReadImpl for Repeat {
+    fn peek_seek(&mut self, state: &mut PeekCursorState, pos: SeekFrom) -> Result<u64> {
+        state.peek_pos = match pos {
+            SeekFrom::Start(offset) => offset,
+            SeekFrom::Current(offset) => seek_add_offset(state.peek_pos, offset)?,
+            SeekFrom::End(_) => {
+                return Err(Error::other(
+                    "cannot seek relative to the end of an endless stream",
+                ))
+            }
+        };
+        Ok(state.peek_pos)
+    }
+
+    fn peek_read(&mut self, state: &mut PeekCursorState, buf: &mut [u8]) -> Result<usize> {
+        let written = self.read(buf)?;
+        state.peek_pos += written as u64;
+        Ok(written)
+    }
+
+    fn peek_fill_buf<'a>(&'a mut self, state: &'a mut PeekCursorState) -> Result<&'a [u8]> {
+        if state.buf.is_empty() {
+            state.buf.resize(REPEAT_FILL_BUF_SIZE, 0);
+            self.read_exact(&mut state.buf)?;
+        }
+        Ok(&state.buf)
+    }
+
+    fn peek_consume(&mut self, state: &mut PeekCursorState, amt: usize) {
+        state.peek_pos += amt as u64;
+    }
+
+    fn peek_commit(&mut self, state: &mut PeekCursorState) -> Result<()> {
+        state.peek_pos = 0;
+        Ok(())
+    }
 }
 
 impl PeekRead for &[u8] {
     fn peek(&mut self) -> PeekCursor<'_> {
         PeekCursor::new(self)
     }
+
+    fn buffered_lookahead(&self) -> usize {
+        self.len()
+    }
 }
 
 impl PeekReadImpl for &[u8] {
@@ -79,12 +220,23 @@ impl PeekReadImpl for &[u8] {
     fn peek_consume(&mut self, state: &mut PeekCursorState, amt: usize) {
         state.peek_pos += amt as u64;
     }
+
+    fn peek_commit(&mut self, state: &mut PeekCursorState) -> Result<()> {
+        *self = self.get(state.peek_pos as usize..).unwrap_or_default();
+        state.peek_pos = 0;
+        Ok(())
+    }
 }
 
 impl<T: AsRef<[u8]>> PeekRead for Cursor<T> {
     fn peek(&mut self) -> PeekCursor<'_> {
         PeekCursor::new(self)
     }
+
+    fn buffered_lookahead(&self) -> usize {
+        let len = self.get_ref().as_ref().len() as u64;
+        len.saturating_sub(self.position()) as usize
+    }
 }
 
 impl<T: AsRef<[u8]>> PeekReadImpl for Cursor<T> {
@@ -118,25 +270,187 @@ impl<T: AsRef<[u8]>> PeekReadImpl for Cursor<T> {
     fn peek_fill_buf<'a>(&'a mut self, state: &'a mut PeekCursorState) -> Result<&'a [u8]> {
         let start_pos = self.stream_position()? as usize;
         let slice = self.get_ref().as_ref();
+        let peek_pos = state.peek_pos.try_into().unwrap_or(usize::MAX);
         Ok(slice
-            .get(start_pos + state.peek_pos as usize..)
+            .get(start_pos.saturating_add(peek_pos)..)
             .unwrap_or_default())
     }
 
     fn peek_consume(&mut self, state: &mut PeekCursorState, amt: usize) {
         state.peek_pos += amt as u64;
     }
+
+    fn peek_commit(&mut self, state: &mut PeekCursorState) -> Result<()> {
+        self.set_position(self.position().saturating_add(state.peek_pos));
+        state.peek_pos = 0;
+        Ok(())
+    }
+}
+
+impl PeekRead for VecDeque<u8> {
+    fn peek(&mut self) -> PeekCursor<'_> {
+        PeekCursor::new(self)
+    }
+
+    fn buffered_lookahead(&self) -> usize {
+        self.len()
+    }
+}
+
+impl PeekReadImpl for VecDeque<u8> {
+    fn peek_seek(&mut self, state: &mut PeekCursorState, pos: SeekFrom) -> Result<u64> {
+        state.peek_pos = match pos {
+            SeekFrom::Start(offset) => offset,
+            SeekFrom::Current(offset) => seek_add_offset(state.peek_pos, offset)?,
+            SeekFrom::End(offset) => seek_add_offset(self.len() as u64, offset)?,
+        };
+        Ok(state.peek_pos)
+    }
+
+    fn peek_read(&mut self, state: &mut PeekCursorState, buf: &mut [u8]) -> Result<usize> {
+        let (mut first, mut second) = vec_deque_slices(self, state.peek_pos);
+        let mut written = first.read(buf).unwrap(); // Can't fail.
+        written += second.read(&mut buf[written..]).unwrap(); // Can't fail.
+        state.peek_pos += written as u64;
+        Ok(written)
+    }
+
+    fn peek_fill_buf(&mut self, state: &mut PeekCursorState) -> Result<&[u8]> {
+        let (first, second) = vec_deque_slices(self, state.peek_pos);
+        Ok(if !first.is_empty() { first } else { second })
+    }
+
+    fn peek_consume(&mut self, state: &mut PeekCursorState, amt: usize) {
+        state.peek_pos += amt as u64;
+    }
+
+    fn peek_commit(&mut self, state: &mut PeekCursorState) -> Result<()> {
+        let len = self.len() as u64;
+        self.drain(..state.peek_pos.min(len) as usize);
+        state.peek_pos = 0;
+        Ok(())
+    }
+}
+
+/// Peeked data is served from the buffer of the [`BufReader`] where possible.
+/// Since data can't be put back into a [`BufReader`], peeking past its buffer
+/// reads from the underlying stream and seeks it back afterwards, which is why
+/// it must implement [`Seek`]. For other streams see [`BufPeekReader`].
+///
+/// The standard library has an unstable inherent `BufReader::peek` method,
+/// call this one as `PeekRead::peek(&mut reader)` if they conflict.
+impl<R: Read + Seek> PeekRead for BufReader<R> {
+    fn peek(&mut self) -> PeekCursor<'_> {
+        PeekCursor::new(self)
+    }
+
+    fn buffered_lookahead(&self) -> usize {
+        self.buffer().len()
+    }
+}
+
+fn offset_to_i64(offset: u64) -> Result<i64> {
+    offset.try_into().map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "invalid seek to a negative or overflowing position",
+        )
+    })
+}
+
+// Reads from the inner stream of a BufReader at the given offset past its
+// buffer, leaving the inner stream where it was.
+fn buf_reader_read_at<R: Read + Seek>(
+    reader: &mut BufReader<R>,
+    offset: u64,
+    buf: &mut [u8],
+) -> Result<usize> {
+    let offset = offset_to_i64(offset)?;
+    let inner = reader.get_mut();
+    inner.seek(SeekFrom::Current(offset))?;
+    let result = inner.read(buf);
+    let read = *result.as_ref().unwrap_or(&0);
+    inner.seek(SeekFrom::Current(-offset - read as i64))?;
+    result
+}
+
+impl<R: Read + Seek> PeekReadImpl for BufReader<R> {
+    fn peek_seek(&mut self, state: &mut PeekCursorState, pos: SeekFrom) -> Result<u64> {
+        state.peek_pos = match pos {
+            SeekFrom::Start(offset) => offset,
+            SeekFrom::Current(offset) => seek_add_offset(state.peek_pos, offset)?,
+            SeekFrom::End(offset) => {
+                let inner = self.get_mut();
+                let cur_pos = inner.stream_position()?;
+                let end_pos = inner.seek(SeekFrom::End(0))?;
+                inner.seek(SeekFrom::Start(cur_pos))?;
+                let len = self.buffer().len() as u64 + end_pos.saturating_sub(cur_pos);
+                seek_add_offset(len, offset)?
+            }
+        };
+        Ok(state.peek_pos)
+    }
+
+    fn peek_read(&mut self, state: &mut PeekCursorState, buf: &mut [u8]) -> Result<usize> {
+        if self.buffer().is_empty() {
+            self.fill_buf()?;
+        }
+
+        let buffered = self.buffer().len() as u64;
+        let written = if state.peek_pos < buffered {
+            self.buffer()[state.peek_pos as usize..]
+                .as_ref()
+                .read(buf)?
+        } else {
+            buf_reader_read_at(self, state.peek_pos - buffered, buf)?
+        };
+        state.peek_pos += written as u64;
+        Ok(written)
+    }
+
+    fn peek_fill_buf<'a>(&'a mut self, state: &'a mut PeekCursorState) -> Result<&'a [u8]> {
+        if self.buffer().is_empty() {
+            self.fill_buf()?;
+        }
+
+        let buffered = self.buffer().len() as u64;
+        if state.peek_pos < buffered {
+            return Ok(&self.buffer()[state.peek_pos as usize..]);
+        }
+
+        state.buf.resize(self.capacity().max(1), 0);
+        let read = buf_reader_read_at(self, state.peek_pos - buffered, &mut state.buf)?;
+        Ok(&state.buf[..read])
+    }
+
+    fn peek_consume(&mut self, state: &mut PeekCursorState, amt: usize) {
+        state.peek_pos += amt as u64;
+    }
+
+    fn peek_commit(&mut self, state: &mut PeekCursorState) -> Result<()> {
+        self.seek_relative(offset_to_i64(state.peek_pos)?)?;
+        state.peek_pos = 0;
+        Ok(())
+    }
 }
 
 impl<T: PeekRead> PeekRead for Take<T> {
     fn peek(&mut self) -> PeekCursor<'_> {
         PeekCursor::new(self)
     }
+
+    fn buffered_lookahead(&self) -> usize {
+        let limit = self.limit().try_into().unwrap_or(usize::MAX);
+        self.get_ref().buffered_lookahead().min(limit)
+    }
 }
 
 impl<T: PeekRead> PeekReadImpl for Take<T> {
     fn peek_seek(&mut self, state: &mut PeekCursorState, pos: SeekFrom) -> Result<u64> {
-        let limit_from_start = state.peek_pos + self.limit();
+        // The limit doesn't change while peeking, it is relative to the read
+        // cursor like the peek position.
+        let limit = self.limit();
+        let old_pos = state.peek_pos;
         state.peek_pos = match pos {
             SeekFrom::Start(offset) => offset,
             SeekFrom::Current(offset) => seek_add_offset(state.peek_pos, offset)?,
@@ -144,8 +458,8 @@ impl<T: PeekRead> PeekReadImpl for Take<T> {
                 // Is there a more efficient way without specialization?
                 let end = {
                     let mut dummy: u8 = 0;
-                    let mut peeker = self.peek();
-                    peeker.seek(SeekFrom::Start(limit_from_start))?;
+                    let mut peeker = self.get_mut().peek();
+                    peeker.seek(SeekFrom::Start(limit))?;
                     let is_eof = peeker.read(std::slice::from_mut(&mut dummy))? == 0;
 
                     if is_eof {
@@ -153,49 +467,68 @@ impl<T: PeekRead> PeekReadImpl for Take<T> {
                         peeker.seek(SeekFrom::Start(0))?;
                         io::copy(&mut peeker, &mut io::sink())?
                     } else {
-                        limit_from_start
+                        limit
                     }
                 };
 
                 seek_add_offset(end, offset)?
             }
         };
-        state.peek_pos = state.peek_pos.min(limit_from_start);
-        self.set_limit(limit_from_start - state.peek_pos);
+        if state.peek_pos != old_pos {
+            state.buf.clear();
+        }
         Ok(state.peek_pos)
     }
 
     fn peek_read(&mut self, state: &mut PeekCursorState, buf: &mut [u8]) -> Result<usize> {
-        if self.limit() == 0 {
+        let remaining = self.limit().saturating_sub(state.peek_pos);
+        if remaining == 0 {
             return Ok(0);
         }
 
-        let limit = self.limit();
-        let mut peeker = self.peek();
+        let mut peeker = self.get_mut().peek();
         peeker.seek(SeekFrom::Start(state.peek_pos))?;
-        let written = peeker.take(limit).read(buf)? as u64;
-        state.peek_pos += written;
-        self.set_limit(limit - written);
-        Ok(written as usize)
+        let written = peeker.take(remaining).read(buf)?;
+        state.peek_pos += written as u64;
+        // What's left of the data from peek_fill_buf can still be consumed.
+        state.buf.drain(..written.min(state.buf.len()));
+        Ok(written)
     }
 
     fn peek_fill_buf<'a>(&'a mut self, state: &'a mut PeekCursorState) -> Result<&'a [u8]> {
-        if self.limit() == 0 {
+        let remaining = self.limit().saturating_sub(state.peek_pos);
+        state.buf.clear();
+        if remaining == 0 {
             return Ok(&[]);
         }
 
-        let mut peeker = self.peek();
+        let mut peeker = self.get_mut().peek();
         peeker.seek(SeekFrom::Start(state.peek_pos))?;
-        let read = peeker.read(&mut state.buf)?;
-        Ok(&state.buf[..read])
+        let available = peeker.fill_buf()?;
+        let n = available
+            .len()
+            .min(remaining.try_into().unwrap_or(usize::MAX));
+        state.buf.extend_from_slice(&available[..n]);
+        Ok(&state.buf)
     }
 
     fn peek_consume(&mut self, state: &mut PeekCursorState, amt: usize) {
-        let limit = self.limit();
-        let limit_from_start = limit + state.peek_pos;
+        // The scratch buffer holds what peek_fill_buf made available, don't
+        // consume past it.
+        let amt = amt.min(state.buf.len());
+        state.buf.drain(..amt);
         state.peek_pos += amt as u64;
-        state.peek_pos = state.peek_pos.min(limit_from_start);
-        self.set_limit(limit_from_start - state.peek_pos);
+    }
+
+    fn peek_commit(&mut self, state: &mut PeekCursorState) -> Result<()> {
+        // Don't consume past the limit from the underlying stream.
+        let committed = state.peek_pos.min(self.limit());
+        let mut peeker = self.get_mut().peek();
+        peeker.seek(SeekFrom::Start(committed))?;
+        peeker.commit()?;
+        self.set_limit(self.limit() - committed);
+        state.peek_pos = 0;
+        Ok(())
     }
 }
 
@@ -0,0 +1,94 @@
+use std::io::Result;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use tokio::io::{AsyncBufRead, AsyncRead, ReadBuf};
+
+use crate::AsyncBufPeekReader;
+#[cfg(doc)]
+use crate::AsyncPeekCursor;
+
+/// An adapter that implements tokio's [`AsyncRead`] for a
+/// [`futures_io::AsyncRead`] stream, so that it can be wrapped in an
+/// [`AsyncBufPeekReader`].
+///
+/// With the `futures-io` feature [`AsyncBufPeekReader`] and
+/// [`AsyncPeekCursor`] implement the [`futures_io`] traits as well, so
+/// streams from the futures ecosystem can be peeked without bridging them by
+/// hand.
+///
+/// ```
+/// # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+/// use futures_util::io::{AsyncReadExt, Cursor};
+/// use peekread::{AsyncBufPeekReader, AsyncPeekRead, FuturesReader};
+/// let mut f = AsyncBufPeekReader::new(FuturesReader::new(Cursor::new("hello world")));
+/// let mut buf = [0; 5];
+/// f.peek().read_exact(&mut buf).await?;
+/// assert_eq!(&buf, b"hello");
+/// let mut s = String::new();
+/// f.read_to_string(&mut s).await?;
+/// assert_eq!(s, "hello world");
+/// # Ok::<(), std::io::Error>(())
+/// # }).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct FuturesReader<R> {
+    inner: R,
+}
+
+impl<R: futures_io::AsyncRead + Unpin> FuturesReader<R> {
+    /// Creates a new [`FuturesReader`].
+    pub fn new(reader: R) -> Self {
+        Self { inner: reader }
+    }
+
+    /// Gets a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Gets a mutable reference to the underlying reader.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Unwraps this `FuturesReader<R>`, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: futures_io::AsyncRead + Unpin> AsyncRead for FuturesReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<()>> {
+        let this = self.get_mut();
+        let read = ready!(Pin::new(&mut this.inner).poll_read(cx, buf.initialize_unfilled()))?;
+        buf.advance(read);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<R: AsyncRead + Unpin> futures_io::AsyncRead for AsyncBufPeekReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize>> {
+        let mut buf = ReadBuf::new(buf);
+        ready!(AsyncRead::poll_read(self, cx, &mut buf))?;
+        Poll::Ready(Ok(buf.filled().len()))
+    }
+}
+
+impl<R: AsyncRead + Unpin> futures_io::AsyncBufRead for AsyncBufPeekReader<R> {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<&[u8]>> {
+        AsyncBufRead::poll_fill_buf(self, cx)
+    }
+
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        AsyncBufRead::consume(self, amt)
+    }
+}
@@ -0,0 +1,157 @@
+use std::io::{Read, Result, Seek};
+
+use crate::{BufPeekReader, PeekCursor, PeekRead, SeekPeekReader};
+
+/// A wrapper for a [`Read`] + [`Seek`] stream that implements [`PeekRead`]
+/// using seeking if the stream actually supports it, and buffering
+/// otherwise.
+///
+/// Some streams implement [`Seek`] but fail when seeking at runtime, e.g. a
+/// [`File`](std::fs::File) that is really a pipe. This reader probes the
+/// stream once when created with a seek that doesn't move it, and then
+/// behaves like a [`SeekPeekReader`] or a [`BufPeekReader`] accordingly.
+///
+/// ```
+/// # use std::io::Cursor;
+/// use peekread::{HybridPeekReader, PeekRead};
+/// let mut f = HybridPeekReader::new(Cursor::new("data"));
+/// assert!(f.is_seeking());
+/// assert!(f.starts_with("da")?);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct HybridPeekReader<R> {
+    inner: Strategy<R>,
+}
+
+#[derive(Debug)]
+enum Strategy<R> {
+    Seek(SeekPeekReader<R>),
+    Buf(BufPeekReader<R>),
+}
+
+impl<R: Read + Seek> HybridPeekReader<R> {
+    /// Creates a new [`HybridPeekReader`], checking whether `reader` can
+    /// seek.
+    pub fn new(mut reader: R) -> Self {
+        let inner = if reader.stream_position().is_ok() {
+            Strategy::Seek(SeekPeekReader::new(reader))
+        } else {
+            Strategy::Buf(BufPeekReader::new(reader))
+        };
+        Self { inner }
+    }
+
+    /// Returns whether peeking is done by seeking, rather than buffering.
+    pub fn is_seeking(&self) -> bool {
+        matches!(self.inner, Strategy::Seek(_))
+    }
+
+    /// Gets a reference to the underlying reader.
+    ///
+    /// It is inadvisable to directly read from the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        match &self.inner {
+            Strategy::Seek(r) => r.get_ref(),
+            Strategy::Buf(r) => r.get_ref(),
+        }
+    }
+
+    /// Gets a mutable reference to the underlying reader.
+    ///
+    /// It is inadvisable to directly read from the underlying reader.
+    pub fn get_mut(&mut self) -> &mut R {
+        match &mut self.inner {
+            Strategy::Seek(r) => r.get_mut(),
+            Strategy::Buf(r) => r.get_mut(),
+        }
+    }
+
+    /// Unwraps this `HybridPeekReader<R>`, returning the underlying reader.
+    ///
+    /// Note that any buffered data is lost.
+    pub fn into_inner(self) -> R {
+        match self.inner {
+            Strategy::Seek(r) => r.into_inner(),
+            Strategy::Buf(r) => r.into_inner(),
+        }
+    }
+}
+
+impl<R: Read + Seek> Read for HybridPeekReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        match &mut self.inner {
+            Strategy::Seek(r) => r.read(buf),
+            Strategy::Buf(r) => r.read(buf),
+        }
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<()> {
+        match &mut self.inner {
+            Strategy::Seek(r) => r.read_exact(buf),
+            Strategy::Buf(r) => r.read_exact(buf),
+        }
+    }
+}
+
+impl<R: Read + Seek> PeekRead for HybridPeekReader<R> {
+    fn peek(&mut self) -> PeekCursor<'_> {
+        match &mut self.inner {
+            Strategy::Seek(r) => r.peek(),
+            Strategy::Buf(r) => r.peek(),
+        }
+    }
+
+    fn buffered_lookahead(&self) -> usize {
+        match &self.inner {
+            Strategy::Seek(r) => r.buffered_lookahead(),
+            Strategy::Buf(r) => r.buffered_lookahead(),
+        }
+    }
+
+    fn skip(&mut self, n: u64) -> Result<u64> {
+        match &mut self.inner {
+            Strategy::Seek(r) => r.skip(n),
+            Strategy::Buf(r) => r.skip(n),
+        }
+    }
+
+    fn consume_matching_prefix<B: AsRef<[u8]>>(&mut self, bytes: B) -> Result<Option<usize>> {
+        match &mut self.inner {
+            Strategy::Seek(r) => r.consume_matching_prefix(bytes),
+            Strategy::Buf(r) => r.consume_matching_prefix(bytes),
+        }
+    }
+
+    fn peek_at(&mut self, offset: u64, buf: &mut [u8]) -> Result<usize> {
+        match &mut self.inner {
+            Strategy::Seek(r) => r.peek_at(offset, buf),
+            Strategy::Buf(r) => r.peek_at(offset, buf),
+        }
+    }
+
+    fn peek_ranges(
+        &mut self,
+        ranges: &[(u64, usize)],
+        out: &mut Vec<u8>,
+    ) -> Result<Vec<(usize, usize)>> {
+        match &mut self.inner {
+            Strategy::Seek(r) => r.peek_ranges(ranges, out),
+            Strategy::Buf(r) => r.peek_ranges(ranges, out),
+        }
+    }
+
+    fn read_prefix(&mut self, n: usize) -> Result<Vec<u8>> {
+        match &mut self.inner {
+            Strategy::Seek(r) => r.read_prefix(n),
+            Strategy::Buf(r) => r.read_prefix(n),
+        }
+    }
+
+    fn peek_entropy_estimate(&mut self, sample: usize) -> Result<f64> {
+        match &mut self.inner {
+            Strategy::Seek(r) => r.peek_entropy_estimate(sample),
+            Strategy::Buf(r) => r.peek_entropy_estimate(sample),
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use std::io::{BufRead, ErrorKind, Result};
+
+use crate::PeekCursor;
+#[cfg(doc)]
+use crate::PeekRead;
+
+/// An iterator over the bytes of a stream read through a peek cursor, see
+/// [`PeekRead::peek_bytes`].
+#[derive(Debug)]
+pub struct PeekBytes<'a> {
+    peeker: PeekCursor<'a>,
+}
+
+impl<'a> PeekBytes<'a> {
+    pub(crate) fn new(peeker: PeekCursor<'a>) -> Self {
+        Self { peeker }
+    }
+
+    /// Unwraps this iterator, returning the peek cursor positioned after the
+    /// last byte yielded.
+    pub fn into_inner(self) -> PeekCursor<'a> {
+        self.peeker
+    }
+}
+
+impl<'a> Iterator for PeekBytes<'a> {
+    type Item = Result<u8>;
+
+    fn next(&mut self) -> Option<Result<u8>> {
+        loop {
+            let byte = match self.peeker.fill_buf() {
+                Ok(buf) => *buf.first()?,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Some(Err(e)),
+            };
+            self.peeker.consume(1);
+            return Some(Ok(byte));
+        }
+    }
+}
+
+/// An iterator over the lines of a stream read through a peek cursor, see
+/// [`PeekRead::peek_lines`].
+#[derive(Debug)]
+pub struct PeekLines<'a> {
+    peeker: PeekCursor<'a>,
+}
+
+impl<'a> PeekLines<'a> {
+    pub(crate) fn new(peeker: PeekCursor<'a>) -> Self {
+        Self { peeker }
+    }
+
+    /// Unwraps this iterator, returning the peek cursor positioned after the
+    /// last line yielded.
+    pub fn into_inner(self) -> PeekCursor<'a> {
+        self.peeker
+    }
+}
+
+impl<'a> Iterator for PeekLines<'a> {
+    type Item = Result<String>;
+
+    fn next(&mut self) -> Option<Result<String>> {
+        let mut line = String::new();
+        match self.peeker.read_line(&mut line) {
+            Ok(0) => None,
+            Ok(_) => {
+                if line.ends_with('\n') {
+                    line.pop();
+                    if line.ends_with('\r') {
+                        line.pop();
+                    }
+                }
+                Some(Ok(line))
+            }
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
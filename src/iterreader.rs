@@ -0,0 +1,135 @@
+use std::collections::VecDeque;
+use std::convert::TryInto;
+use std::io::{Error, Read, Result, SeekFrom};
+use std::iter::Fuse;
+
+use crate::detail::{PeekCursorState, PeekReadImpl};
+use crate::util::{seek_add_offset, vec_deque_slices};
+use crate::{PeekCursor, PeekRead};
+
+/// A [`Read`] stream of the bytes produced by an iterator, which implements
+/// [`PeekRead`] by buffering the bytes that were peeked.
+///
+/// Useful to test parsers against generated or endless data without
+/// collecting it first. Seeking a peek cursor relative to the end consumes
+/// the whole iterator, so it's an error for iterators that don't report an
+/// upper bound in their [`Iterator::size_hint`], as they may not end.
+///
+/// ```
+/// # use std::io::Read;
+/// use peekread::{IterPeekReader, PeekRead};
+/// let mut f = IterPeekReader::new((0..).map(|i| b'a' + i % 26));
+/// assert!(f.starts_with("abc")?);
+/// let mut buf = [0; 28];
+/// f.read_exact(&mut buf)?;
+/// assert_eq!(&buf[24..], b"yzab");
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct IterPeekReader<I> {
+    // The bytes produced by the iterator that were peeked but not yet read.
+    buf: VecDeque<u8>,
+    iter: Fuse<I>,
+}
+
+impl<I: Iterator<Item = u8>> IterPeekReader<I> {
+    // How many bytes fill_buf on a peek cursor makes available at least.
+    const FILL_BUF_SIZE: usize = 64;
+
+    /// Creates a new [`IterPeekReader`].
+    pub fn new<T: IntoIterator<IntoIter = I>>(iter: T) -> Self {
+        Self {
+            buf: VecDeque::new(),
+            iter: iter.into_iter().fuse(),
+        }
+    }
+
+    /// Unwraps this `IterPeekReader<I>`, returning the iterator.
+    ///
+    /// Note that any bytes that were peeked but not yet read are lost.
+    pub fn into_inner(self) -> Fuse<I> {
+        self.iter
+    }
+
+    // Takes bytes from the iterator until at least nbytes are buffered or
+    // the iterator ends.
+    fn request_buffer(&mut self, nbytes: usize) {
+        let needed = nbytes.saturating_sub(self.buf.len());
+        self.buf.extend(self.iter.by_ref().take(needed));
+    }
+}
+
+impl<I: Iterator<Item = u8>> Read for IterPeekReader<I> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let (mut first, mut second) = self.buf.as_slices();
+        let mut written = first.read(buf).unwrap(); // Can't fail.
+        written += second.read(&mut buf[written..]).unwrap(); // Can't fail.
+        self.buf.drain(..written);
+        for (slot, byte) in buf[written..].iter_mut().zip(self.iter.by_ref()) {
+            *slot = byte;
+            written += 1;
+        }
+        Ok(written)
+    }
+}
+
+impl<I: Iterator<Item = u8>> PeekRead for IterPeekReader<I> {
+    fn peek(&mut self) -> PeekCursor<'_> {
+        PeekCursor::new(self)
+    }
+
+    fn buffered_lookahead(&self) -> usize {
+        self.buf.len()
+    }
+}
+
+impl<I: Iterator<Item = u8>> PeekReadImpl for IterPeekReader<I> {
+    fn peek_seek(&mut self, state: &mut PeekCursorState, pos: SeekFrom) -> Result<u64> {
+        state.peek_pos = match pos {
+            SeekFrom::Start(offset) => offset,
+            SeekFrom::Current(offset) => seek_add_offset(state.peek_pos, offset)?,
+            SeekFrom::End(offset) => {
+                if self.iter.size_hint().1.is_none() {
+                    return Err(Error::other(
+                        "cannot seek relative to the end of a possibly endless iterator",
+                    ));
+                }
+                self.buf.extend(self.iter.by_ref());
+                seek_add_offset(self.buf.len() as u64, offset)?
+            }
+        };
+        Ok(state.peek_pos)
+    }
+
+    fn peek_read(&mut self, state: &mut PeekCursorState, buf: &mut [u8]) -> Result<usize> {
+        let peek_pos: usize = state.peek_pos.try_into().unwrap_or(usize::MAX);
+        self.request_buffer(peek_pos.saturating_add(buf.len()));
+        let (mut first, mut second) = vec_deque_slices(&self.buf, state.peek_pos);
+        let mut written = first.read(buf).unwrap(); // Can't fail.
+        written += second.read(&mut buf[written..]).unwrap(); // Can't fail.
+        state.peek_pos += written as u64;
+        Ok(written)
+    }
+
+    fn peek_fill_buf(&mut self, state: &mut PeekCursorState) -> Result<&[u8]> {
+        let peek_pos: usize = state.peek_pos.try_into().unwrap_or(usize::MAX);
+        self.request_buffer(peek_pos.saturating_add(Self::FILL_BUF_SIZE));
+        let (first, second) = vec_deque_slices(&self.buf, state.peek_pos);
+        Ok(if !first.is_empty() { first } else { second })
+    }
+
+    fn peek_consume(&mut self, state: &mut PeekCursorState, amt: usize) {
+        state.peek_pos += amt as u64;
+    }
+
+    fn peek_commit(&mut self, state: &mut PeekCursorState) -> Result<()> {
+        let buffered = (self.buf.len() as u64).min(state.peek_pos);
+        self.buf.drain(..buffered as usize);
+        self.iter
+            .by_ref()
+            .take((state.peek_pos - buffered).try_into().unwrap_or(usize::MAX))
+            .for_each(drop);
+        state.peek_pos = 0;
+        Ok(())
+    }
+}
@@ -1,6 +1,7 @@
 #![doc(html_root_url = "https://docs.rs/peekread/0.1.1")]
+#![cfg_attr(feature = "read_buf", feature(read_buf, core_io_borrowed_buf))]
 #![warn(
-    invalid_html_tags,
+    rustdoc::invalid_html_tags,
     missing_debug_implementations,
     trivial_casts,
     trivial_numeric_casts,
@@ -39,7 +40,7 @@
 //! let mut f = SeekPeekReader::new(File::open("ambiguous")?);
 //!
 //! // HTML is so permissive its parser never fails, so check for signature.
-//! if f.starts_with("<!DOCTYPE html>\n") {
+//! if f.starts_with("<!DOCTYPE html>\n")? {
 //!     Ok(ParseResult::Html(parse_as_html(f)))
 //! } else {
 //!     // Can pass PeekCursor to functions accepting T: Read without them
@@ -57,23 +58,76 @@
 /// Details for those wishing to implement [`PeekRead`].
 pub mod detail;
 
+#[cfg(feature = "tokio")]
+mod asyncbufreader;
+#[cfg(feature = "tokio")]
+mod asyncpeekread;
 mod bufreader;
+mod checkedreader;
+mod ext;
 mod foreign_impl;
+#[cfg(feature = "futures-io")]
+mod futuresio;
+mod hybridreader;
+mod iter;
+mod iterreader;
+mod mapreader;
+mod ownedcursor;
 mod seekreader;
+#[cfg(feature = "tempfile")]
+mod spillreader;
+mod stdio;
+mod transaction;
 mod util;
 
-pub use bufreader::BufPeekReader;
-pub use detail::cursor::PeekCursor;
+#[cfg(feature = "tokio")]
+pub use asyncbufreader::AsyncBufPeekReader;
+#[cfg(feature = "tokio")]
+pub use asyncpeekread::AsyncPeekRead;
+pub use bufreader::{BufPeekReader, LookaheadGuard};
+pub use checkedreader::CheckedPeekReader;
+#[cfg(feature = "tokio")]
+pub use detail::async_cursor::AsyncPeekCursor;
+pub use detail::cursor::{PeekCheckpoint, PeekCursor};
+pub use ext::{Bom, PeekReadExt};
+#[cfg(feature = "futures-io")]
+pub use futuresio::FuturesReader;
+pub use hybridreader::HybridPeekReader;
+pub use iter::{PeekBytes, PeekLines};
+pub use iterreader::IterPeekReader;
+pub use mapreader::MapBytesPeekReader;
+pub use ownedcursor::OwnedPeekCursor;
 pub use seekreader::SeekPeekReader;
+#[cfg(feature = "tempfile")]
+pub use spillreader::SpillingPeekReader;
+use std::convert::TryInto;
 #[cfg(doc)]
-use std::io::{BufRead, BufReader, Seek};
-use std::io::{Read, Result};
+use std::io::BufReader;
+use std::io::{BufRead, ErrorKind, Read, Result, Seek, SeekFrom, Take, Write};
+pub use stdio::stdin;
+pub use transaction::Transaction;
 
 /// A trait for a [`Read`] stream that supports peeking ahead in the stream.
 ///
 /// In addition to a normal read cursor it can create a separate 'peek cursor'
 /// which can go ahead of the regular read cursor, but never behind it. Reading
 /// from the peek cursor does not affect the read cursor in any way.
+///
+/// In-memory data can be peeked through `&[u8]`, [`Cursor`](std::io::Cursor)
+/// or [`VecDeque<u8>`](std::collections::VecDeque). Other byte containers,
+/// such as arrays or `Cow<[u8]>`, don't implement [`Read`] themselves, so
+/// wrap them in a [`Cursor`](std::io::Cursor):
+///
+/// ```
+/// # use std::borrow::Cow;
+/// # use std::io::Cursor;
+/// use peekread::PeekRead;
+/// let mut f = Cursor::new(*b"\x89PNG\r\n\x1a\n");
+/// assert!(f.starts_with(b"\x89PNG")?);
+/// let mut f = Cursor::new(Cow::Borrowed(&b"GIF89a"[..]));
+/// assert!(f.starts_with("GIF")?);
+/// # Ok::<(), std::io::Error>(())
+/// ```
 pub trait PeekRead: Read {
     /// Returns a [`PeekCursor`] which implements [`BufRead`] + [`Seek`],
     /// allowing you to peek ahead in a stream of data. Reading from this or
@@ -93,38 +147,761 @@ pub trait PeekRead: Read {
     /// reads from this particular peek cursor.
     fn peek(&mut self) -> PeekCursor<'_>;
 
+    /// Creates a new reader that starts at the current read cursor and can
+    /// be read from and peeked into independently of this stream.
+    ///
+    /// The fork reads through a [`PeekCursor`] on this stream, so any data it
+    /// pulls from the underlying stream remains available to this stream
+    /// afterwards (for [`BufPeekReader`] it is served from the same buffer).
+    /// Since the fork borrows this stream mutably only one fork can be live
+    /// at a time, and this stream can only continue once the fork is
+    /// dropped. Reading from the fork never advances this stream.
+    ///
+    /// ```
+    /// # use std::io::Read;
+    /// use peekread::{BufPeekReader, PeekRead};
+    /// let mut f = BufPeekReader::new(&b"GIF89a..."[..]);
+    /// assert!(f.fork().consume_prefix("GIF")?);
+    /// assert!(f.fork().consume_prefix("GIF89a")?);
+    ///
+    /// let mut s = String::new();
+    /// f.read_to_string(&mut s)?;
+    /// assert_eq!(s, "GIF89a...");
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    fn fork(&mut self) -> BufPeekReader<PeekCursor<'_>> {
+        BufPeekReader::new(self.peek())
+    }
+
+    /// Like [`PeekRead::peek`], but the returned cursor takes ownership of the
+    /// stream, so it can be returned from the function that created the
+    /// stream. The stream can be recovered with
+    /// [`OwnedPeekCursor::into_inner`], with its read cursor unchanged.
+    ///
+    /// ```
+    /// # use std::io::{BufRead, Cursor};
+    /// use peekread::{OwnedPeekCursor, PeekRead, SeekPeekReader};
+    /// fn open() -> OwnedPeekCursor<SeekPeekReader<Cursor<&'static str>>> {
+    ///     SeekPeekReader::new(Cursor::new("line\nrest")).into_peek()
+    /// }
+    ///
+    /// let mut peeker = open();
+    /// let mut line = String::new();
+    /// peeker.read_line(&mut line)?;
+    /// assert_eq!(line, "line\n");
+    /// let mut f = peeker.into_inner();
+    /// assert!(f.starts_with("line")?);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    fn into_peek(self) -> OwnedPeekCursor<Self>
+    where
+        Self: Sized,
+    {
+        OwnedPeekCursor::new(self)
+    }
+
+    /// Returns a view of at most the next `limit` bytes of the stream that
+    /// can itself be peeked, borrowing the stream instead of taking it like
+    /// [`Read::take`] does. Reading from the view advances the stream, while
+    /// peeking into it doesn't, and neither reaches past the limit.
+    ///
+    /// ```
+    /// # use std::io::Read;
+    /// use peekread::PeekRead;
+    /// let mut f = &b"5:hello, world"[..];
+    /// f.read_exact(&mut [0; 2])?;
+    /// let mut field = f.peek_take(5);
+    /// assert!(field.starts_with("hello")?);
+    /// assert!(!field.starts_with("hello,")?);
+    /// let mut s = String::new();
+    /// field.read_to_string(&mut s)?;
+    /// assert_eq!(s, "hello");
+    /// assert_eq!(f, b", world");
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    fn peek_take(&mut self, limit: u64) -> Take<&mut Self> {
+        Read::take(self, limit)
+    }
+
+    /// Returns how many bytes past the read cursor are already in memory and
+    /// can be peeked without doing any I/O. Streams that don't buffer return
+    /// 0.
+    fn buffered_lookahead(&self) -> usize {
+        0
+    }
+
+    /// Advances the read cursor by `n` bytes, or until the end of the stream.
+    /// Returns the number of bytes skipped.
+    fn skip(&mut self, n: u64) -> Result<u64> {
+        std::io::copy(&mut self.take(n), &mut std::io::sink())
+    }
+
     /// Convenience method to check if the upcoming bytes in a stream equal the
     /// given string of bytes, without advancing the stream.
     fn starts_with<B: AsRef<[u8]>>(&mut self, bytes: B) -> Result<bool> {
-        let bytes = bytes.as_ref();
-        let mut buf = [0u8; 32]; // Prevent allocation, check 32 bytes at a time.
+        util::peek_starts_with(self, bytes.as_ref(), |a, b| a == b)
+    }
+
+    /// Like [`PeekRead::starts_with`], but ignoring ASCII case. Only the ASCII
+    /// letters `a-z` and `A-Z` are folded, all other bytes must match
+    /// exactly.
+    fn starts_with_ignore_ascii_case<B: AsRef<[u8]>>(&mut self, bytes: B) -> Result<bool> {
+        util::peek_starts_with(self, bytes.as_ref(), <[u8]>::eq_ignore_ascii_case)
+    }
+
+    /// Convenience method to check which of the given strings of bytes, if
+    /// any, the upcoming bytes in a stream start with, without advancing the
+    /// stream. Returns the index of the first matching candidate.
+    ///
+    /// Uses a single peek cursor, peeking only as far as needed for the
+    /// candidates checked before finding a match.
+    fn starts_with_any<B: AsRef<[u8]>>(&mut self, candidates: &[B]) -> Result<Option<usize>> {
+        let mut peeked = Vec::new();
         let mut peeker = self.peek();
-        for chunk in bytes.chunks(32) {
-            let partial_buf = &mut buf[..chunk.len()];
-            if let Err(e) = peeker.read_exact(partial_buf) {
-                return match e.kind() {
-                    std::io::ErrorKind::UnexpectedEof => Ok(false),
-                    _ => Err(e),
-                };
+        for (i, candidate) in candidates.iter().enumerate() {
+            let candidate = candidate.as_ref();
+            if candidate.len() > peeked.len() {
+                let needed = candidate.len() - peeked.len();
+                peeker
+                    .by_ref()
+                    .take(needed as u64)
+                    .read_to_end(&mut peeked)?;
             }
 
-            if partial_buf != chunk {
-                return Ok(false);
+            if peeked.starts_with(candidate) {
+                return Ok(Some(i));
             }
         }
 
-        Ok(true)
+        Ok(None)
+    }
+
+    /// Returns how many leading bytes of the stream equal those of `bytes`,
+    /// stopping at the first mismatch or the end of the stream, without
+    /// advancing the stream. Peeks at most `bytes.len()` bytes.
+    ///
+    /// ```
+    /// # use std::io::Cursor;
+    /// use peekread::{PeekRead, SeekPeekReader};
+    /// let mut f = SeekPeekReader::new(Cursor::new("GIF87a"));
+    /// assert_eq!(f.common_prefix_len("GIF89a")?, 4);
+    /// assert_eq!(f.common_prefix_len("GIF87a, or more")?, 6);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    fn common_prefix_len<B: AsRef<[u8]>>(&mut self, bytes: B) -> Result<usize> {
+        util::peek_common_prefix_len(self, bytes.as_ref())
     }
 
     /// Convenience method to consume a specific string of bytes if they are
     /// next up in the stream, leaving the stream unchanged otherwise. Returns
     /// whether the string was found and removed.
+    ///
+    /// A prefix that runs past the end of the stream is never a match, so
+    /// nothing is consumed in that case:
+    /// ```
+    /// # use std::io::Read;
+    /// use peekread::PeekRead;
+    /// let mut f = &b"abc"[..];
+    /// assert!(!f.consume_prefix("abcd")?);
+    /// assert!(f.consume_prefix("abc")?);
+    /// assert_eq!(f.read(&mut [0; 1])?, 0);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
     fn consume_prefix<B: AsRef<[u8]>>(&mut self, bytes: B) -> Result<bool> {
+        Ok(self.consume_matching_prefix(bytes)?.is_some())
+    }
+
+    /// Like [`PeekRead::consume_prefix`], but returns the number of bytes
+    /// consumed if the prefix matched, or `None` if it didn't.
+    fn consume_matching_prefix<B: AsRef<[u8]>>(&mut self, bytes: B) -> Result<Option<usize>> {
         let bytes = bytes.as_ref();
-        let should_strip = self.starts_with(bytes)?;
+        if !self.starts_with(bytes)? {
+            return Ok(None);
+        }
+        self.skip(bytes.len() as u64)?;
+        Ok(Some(bytes.len()))
+    }
+
+    /// Like [`PeekRead::consume_prefix`], but ignoring ASCII case. See
+    /// [`PeekRead::starts_with_ignore_ascii_case`].
+    fn consume_prefix_ignore_ascii_case<B: AsRef<[u8]>>(&mut self, bytes: B) -> Result<bool> {
+        let bytes = bytes.as_ref();
+        let should_strip = self.starts_with_ignore_ascii_case(bytes)?;
         if should_strip {
-            std::io::copy(&mut self.take(bytes.len() as u64), &mut std::io::sink())?;
+            self.skip(bytes.len() as u64)?;
         }
         Ok(should_strip)
     }
+
+    /// Convenience method to consume whichever of the given strings of bytes
+    /// the stream starts with, leaving the stream unchanged if there is none.
+    /// Returns the index of the candidate that was removed.
+    ///
+    /// If several candidates match the longest one wins, and among equally
+    /// long ones the first.
+    ///
+    /// ```
+    /// use peekread::PeekRead;
+    /// let mut f = &b"<=>"[..];
+    /// assert_eq!(f.consume_prefix_any(&["<", "<=", "!="])?, Some(1));
+    /// assert_eq!(f, b">");
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    fn consume_prefix_any<B: AsRef<[u8]>>(&mut self, candidates: &[B]) -> Result<Option<usize>> {
+        let max_len = candidates
+            .iter()
+            .map(|c| c.as_ref().len())
+            .max()
+            .unwrap_or(0);
+        let mut peeked = Vec::new();
+        self.peek().take(max_len as u64).read_to_end(&mut peeked)?;
+
+        let mut best: Option<(usize, usize)> = None;
+        for (i, candidate) in candidates.iter().enumerate() {
+            let len = candidate.as_ref().len();
+            if peeked.starts_with(candidate.as_ref()) && best.map_or(true, |(_, l)| len > l) {
+                best = Some((i, len));
+            }
+        }
+
+        if let Some((_, len)) = best {
+            self.skip(len as u64)?;
+        }
+        Ok(best.map(|(i, _)| i))
+    }
+
+    /// Checks whether the bytes starting `offset` bytes past the read cursor
+    /// are `bytes`, without advancing the stream. Returns false if the stream
+    /// ends first.
+    fn matches_at<B: AsRef<[u8]>>(&mut self, offset: u64, bytes: B) -> Result<bool> {
+        let mut peeker = self.peek();
+        peeker.seek(SeekFrom::Start(offset))?;
+        util::read_starts_with(&mut peeker, bytes.as_ref(), |a, b| a == b)
+    }
+
+    /// Returns the next byte in the stream without advancing the stream, or
+    /// `None` if the stream has ended.
+    fn peek_byte(&mut self) -> Result<Option<u8>> {
+        let mut peeker = self.peek();
+        loop {
+            match peeker.fill_buf() {
+                Ok(buf) => return Ok(buf.first().copied()),
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Copies up to `n` bytes from the stream into `out` without advancing
+    /// the stream, stopping early if the stream ends. Returns the number of
+    /// bytes copied.
+    fn peek_copy<W: Write + ?Sized>(&mut self, n: u64, out: &mut W) -> Result<u64> {
+        let mut copied = 0;
+        let mut peeker = self.peek().take(n);
+        loop {
+            let chunk = match peeker.fill_buf() {
+                Ok(chunk) => chunk,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            if chunk.is_empty() {
+                return Ok(copied);
+            }
+
+            out.write_all(chunk)?;
+            let n = chunk.len();
+            peeker.consume(n);
+            copied += n as u64;
+        }
+    }
+
+    /// Returns an iterator over the bytes of the stream without advancing
+    /// the stream. Each byte is taken from [`BufRead::fill_buf`] of a single
+    /// peek cursor, so this is efficient even for streams that aren't
+    /// buffered.
+    ///
+    /// ```
+    /// use peekread::PeekRead;
+    /// let mut f = &b"123+4"[..];
+    /// let digits = f.peek_bytes().take_while(|b| matches!(b, Ok(b'0'..=b'9'))).count();
+    /// assert_eq!(digits, 3);
+    /// assert_eq!(f, b"123+4");
+    /// ```
+    fn peek_bytes(&mut self) -> PeekBytes<'_> {
+        PeekBytes::new(self.peek())
+    }
+
+    /// Returns whether the stream has ended, i.e. there are no bytes left
+    /// after the read cursor. Does not advance the stream.
+    fn peek_is_eof(&mut self) -> Result<bool> {
+        Ok(self.peek_byte()?.is_none())
+    }
+
+    /// Like [`Read::read_exact`] on a peek cursor, but instead of failing
+    /// with [`ErrorKind::UnexpectedEof`] when the stream ends early, returns
+    /// how many bytes it managed to read. Does not advance the stream. The
+    /// same as [`Self::peek_at`] with an offset of 0.
+    ///
+    /// ```
+    /// use peekread::PeekRead;
+    /// let mut f = &b"\x01\x02\x03"[..];
+    /// let mut trailer = [0; 4];
+    /// assert_eq!(f.peek_read_exact_or_len(&mut trailer)?, 3);
+    /// assert_eq!(&trailer[..3], b"\x01\x02\x03");
+    /// assert_eq!(f.len(), 3);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    fn peek_read_exact_or_len(&mut self, buf: &mut [u8]) -> Result<usize> {
+        util::read_fully(&mut self.peek(), buf)
+    }
+
+    /// Peeks a frame made of a `header_len` byte header followed by a
+    /// payload, whose length `parse_len` computes from the header. Returns
+    /// the whole frame, header included, or `None` if the stream ends before
+    /// the frame does. Does not advance the stream, so this tells whether a
+    /// complete frame is available before reading it.
+    ///
+    /// ```
+    /// # use std::io::Read;
+    /// use peekread::PeekRead;
+    /// let mut f = &b"\x00\x02hi\x00\x05hel"[..];
+    /// let be_len = |h: &[u8]| u16::from_be_bytes([h[0], h[1]]) as u64;
+    /// assert_eq!(f.peek_frame(2, be_len)?.as_deref(), Some(&b"\x00\x02hi"[..]));
+    /// f.read_exact(&mut [0; 4])?;
+    /// assert_eq!(f.peek_frame(2, be_len)?, None);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    fn peek_frame<F: FnOnce(&[u8]) -> u64>(
+        &mut self,
+        header_len: usize,
+        parse_len: F,
+    ) -> Result<Option<Vec<u8>>> {
+        let mut peeker = self.peek();
+        let mut frame = vec![0; header_len];
+        if util::read_fully(&mut peeker, &mut frame)? < header_len {
+            return Ok(None);
+        }
+
+        // Don't trust the length for the allocation, the stream may be shorter.
+        let payload_len = parse_len(&frame);
+        let read = Read::take(&mut peeker, payload_len).read_to_end(&mut frame)?;
+        Ok(if read as u64 == payload_len {
+            Some(frame)
+        } else {
+            None
+        })
+    }
+
+    /// Reads into `buf` starting `offset` bytes past the read cursor, without
+    /// advancing the stream. Returns the number of bytes read, which is less
+    /// than `buf.len()` only if the end of the stream was reached.
+    fn peek_at(&mut self, offset: u64, buf: &mut [u8]) -> Result<usize> {
+        let mut peeker = self.peek();
+        peeker.seek(SeekFrom::Start(offset))?;
+        util::read_fully(&mut peeker, buf)
+    }
+
+    /// Appends the bytes of several ranges of the stream to `out` without
+    /// advancing the stream, e.g. to check the fields of a table of offsets
+    /// in one go. Each range is given as an offset past the read cursor and a
+    /// length, and the returned `(start, end)` pairs give where each range
+    /// ended up in `out`. Ranges going past the end of the stream are cut
+    /// short.
+    ///
+    /// ```
+    /// use peekread::PeekRead;
+    /// let mut f = &b"magic...fmt...data"[..];
+    /// let mut out = Vec::new();
+    /// let spans = f.peek_ranges(&[(8, 3), (14, 4), (0, 5)], &mut out)?;
+    /// assert_eq!(&out[spans[0].0..spans[0].1], b"fmt");
+    /// assert_eq!(&out[spans[1].0..spans[1].1], b"data");
+    /// assert_eq!(&out[spans[2].0..spans[2].1], b"magic");
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    fn peek_ranges(
+        &mut self,
+        ranges: &[(u64, usize)],
+        out: &mut Vec<u8>,
+    ) -> Result<Vec<(usize, usize)>> {
+        let mut peeker = self.peek();
+        let mut spans = Vec::with_capacity(ranges.len());
+        for &(offset, len) in ranges {
+            peeker.seek(SeekFrom::Start(offset))?;
+            let start = out.len();
+            peeker.by_ref().take(len as u64).read_to_end(out)?;
+            spans.push((start, out.len()));
+        }
+        Ok(spans)
+    }
+
+    /// Reads the next line of the stream into `buf` without advancing the
+    /// stream, exactly like [`BufRead::read_line`] would. Returns the number
+    /// of bytes read.
+    fn peek_line(&mut self, buf: &mut String) -> Result<usize> {
+        self.peek().read_line(buf)
+    }
+
+    /// Returns an iterator over the lines of the stream, like
+    /// [`BufRead::lines`], without advancing the stream. Only the peek cursor
+    /// held by the iterator moves as it is consumed.
+    ///
+    /// ```
+    /// use peekread::PeekRead;
+    /// let mut f = &b"GET / HTTP/1.1\r\nHost: example.com\r\n"[..];
+    /// let lines: Vec<String> = f.peek_lines().collect::<Result<_, _>>()?;
+    /// assert_eq!(lines, ["GET / HTTP/1.1", "Host: example.com"]);
+    /// assert_eq!(f.len(), 35);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    fn peek_lines(&mut self) -> PeekLines<'_> {
+        PeekLines::new(self.peek())
+    }
+
+    /// Reads everything up to and including the first `delim` byte of the
+    /// stream into `buf` without advancing the stream, exactly like
+    /// [`BufRead::read_until`] would. Returns the number of bytes read.
+    fn peek_until(&mut self, delim: u8, buf: &mut Vec<u8>) -> Result<usize> {
+        self.peek().read_until(delim, buf)
+    }
+
+    /// Returns the offset from the read cursor of the first occurrence of
+    /// `needle` that lies entirely within the next `max` bytes of the stream,
+    /// or `None` if there is none, without advancing the stream.
+    ///
+    /// The needle may straddle the chunks the stream is read in:
+    /// ```
+    /// use peekread::{BufPeekReader, PeekRead};
+    /// let mut f = BufPeekReader::new(&b"ab\r\n\r\ncd"[..]);
+    /// // Buffered in chunks of "ab\r", "\n\r\n" and "cd".
+    /// f.set_min_read_size(3);
+    /// f.set_max_read_size(Some(3));
+    /// assert_eq!(f.peek_find_seq(b"\r\n\r\n", 5)?, None);
+    /// assert_eq!(f.peek_find_seq(b"\r\n\r\n", 6)?, Some(2));
+    /// assert_eq!(f.peek_find_seq(b"cd", 1024)?, Some(6));
+    /// assert_eq!(f.peek_find_seq(b"x", 1024)?, None);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    ///
+    /// With a `max` of 0 nothing is read from the stream:
+    /// ```
+    /// use peekread::{BufPeekReader, PeekRead};
+    /// let mut f = BufPeekReader::new(&b"abc"[..]);
+    /// assert_eq!(f.peek_find_seq(b"a", 0)?, None);
+    /// assert!(f.buffer().is_empty());
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    fn peek_find_seq(&mut self, needle: &[u8], max: u64) -> Result<Option<u64>> {
+        if needle.is_empty() {
+            return Ok(Some(0));
+        }
+
+        // Keep the last needle.len() - 1 bytes around between chunks, so we
+        // can find needles straddling the chunk boundary.
+        let mut window = Vec::new();
+        let mut window_start = 0u64;
+        let mut peeker = self.peek();
+        loop {
+            let remaining = max - (window_start + window.len() as u64);
+            if remaining == 0 {
+                return Ok(None);
+            }
+            let chunk = match peeker.fill_buf() {
+                Ok(chunk) => chunk,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            if chunk.is_empty() {
+                return Ok(None);
+            }
+
+            let n = chunk.len().min(remaining.try_into().unwrap_or(usize::MAX));
+            window.extend_from_slice(&chunk[..n]);
+            peeker.consume(n);
+            if let Some(i) = window.windows(needle.len()).position(|w| w == needle) {
+                return Ok(Some(window_start + i as u64));
+            }
+
+            let discard = window.len().saturating_sub(needle.len() - 1);
+            window.drain(..discard);
+            window_start += discard as u64;
+        }
+    }
+
+    /// Returns the offset from the read cursor of the first occurrence of
+    /// `needle` in the stream, or `None` if the stream ends without one, without
+    /// advancing the stream. The stream is only peeked as far as needed to
+    /// find the needle. See [`PeekRead::peek_find_seq`] to limit how far ahead
+    /// to look.
+    fn peek_find<B: AsRef<[u8]>>(&mut self, needle: B) -> Result<Option<u64>> {
+        self.peek_find_seq(needle.as_ref(), u64::MAX)
+    }
+
+    /// Returns the offset from the read cursor of the first occurrence of
+    /// `byte` in the stream, or `None` if the stream ends without one, without
+    /// advancing the stream. Faster than [`PeekRead::peek_find`] with a single
+    /// byte needle.
+    fn peek_find_byte(&mut self, byte: u8) -> Result<Option<u64>> {
+        let mut offset = 0;
+        let mut peeker = self.peek();
+        loop {
+            let chunk = match peeker.fill_buf() {
+                Ok(chunk) => chunk,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            if chunk.is_empty() {
+                return Ok(None);
+            }
+
+            if let Some(i) = chunk.iter().position(|&b| b == byte) {
+                return Ok(Some(offset + i as u64));
+            }
+            let n = chunk.len();
+            peeker.consume(n);
+            offset += n as u64;
+        }
+    }
+
+    /// Convenience method to consume everything up to and including the first
+    /// occurrence of `needle` within the next `max` bytes of the stream. See
+    /// [`PeekRead::peek_find_seq`]. Returns the number of bytes consumed, or
+    /// `None` if the needle was not found, leaving the stream unchanged.
+    ///
+    /// ```
+    /// # use std::io::Read;
+    /// use peekread::PeekRead;
+    /// let mut f = &b"Host: a\r\n\r\nbody"[..];
+    /// assert_eq!(f.consume_through_seq(b"\r\n\r\n", 8)?, None);
+    /// assert_eq!(f.consume_through_seq(b"\r\n\r\n", 1024)?, Some(11));
+    /// assert_eq!(f, b"body");
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    fn consume_through_seq(&mut self, needle: &[u8], max: u64) -> Result<Option<u64>> {
+        let offset = match self.peek_find_seq(needle, max)? {
+            Some(offset) => offset,
+            None => return Ok(None),
+        };
+        let len = offset + needle.len() as u64;
+        self.skip(len)?;
+        Ok(Some(len))
+    }
+
+    /// Consumes bytes from the stream for as long as `pred` returns true for
+    /// them, stopping at the first byte it returns false for (which is left
+    /// in the stream) or at the end of the stream. Returns the number of bytes
+    /// consumed.
+    ///
+    /// ```
+    /// # use std::io::Read;
+    /// use peekread::{BufPeekReader, PeekRead};
+    /// let mut f = BufPeekReader::new(&b"   \tkey"[..]);
+    /// assert_eq!(f.consume_while(|b| b.is_ascii_whitespace())?, 4);
+    /// assert!(f.consume_prefix("key")?);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    fn consume_while<F: FnMut(u8) -> bool>(&mut self, mut pred: F) -> Result<u64> {
+        let mut len = 0;
+        let mut peeker = self.peek();
+        loop {
+            let chunk = match peeker.fill_buf() {
+                Ok(chunk) => chunk,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            if chunk.is_empty() {
+                break;
+            }
+
+            let n = chunk.iter().position(|&b| !pred(b));
+            let matched = n.unwrap_or(chunk.len());
+            peeker.consume(matched);
+            len += matched as u64;
+            if n.is_some() {
+                break;
+            }
+        }
+
+        drop(peeker);
+        self.skip(len)?;
+        Ok(len)
+    }
+
+    /// Consumes bytes from the stream up to, but not including, the next
+    /// occurrence of `delim`, or up to the end of the stream if there is
+    /// none. Returns the number of bytes consumed.
+    ///
+    /// ```
+    /// use peekread::{BufPeekReader, PeekRead};
+    /// let mut f = BufPeekReader::new(&b"ignored\nnext"[..]);
+    /// assert_eq!(f.consume_until(b'\n')?, 7);
+    /// assert!(f.consume_prefix("\nnext")?);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    fn consume_until(&mut self, delim: u8) -> Result<u64> {
+        self.consume_while(|b| b != delim)
+    }
+
+    /// Reads exactly the next `n` bytes into a new [`Vec`], advancing the
+    /// stream past them. Fails with [`ErrorKind::UnexpectedEof`] if the
+    /// stream ends first, in which case the bytes that were read are lost,
+    /// like with [`Read::read_exact`].
+    ///
+    /// ```
+    /// # use std::io::Read;
+    /// use peekread::{BufPeekReader, PeekRead};
+    /// let mut f = BufPeekReader::new(&b"HDR1payload"[..]);
+    /// assert_eq!(f.read_prefix(4)?, b"HDR1");
+    /// assert!(f.starts_with("payload")?);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    fn read_prefix(&mut self, n: usize) -> Result<Vec<u8>> {
+        let mut prefix = vec![0; n];
+        self.read_exact(&mut prefix)?;
+        Ok(prefix)
+    }
+
+    /// Estimates how random the next `sample` bytes of the stream are, without
+    /// advancing the stream. Useful as a cheap check whether data is already
+    /// compressed or encrypted.
+    ///
+    /// The estimate is the Shannon entropy of the byte frequencies in the
+    /// sample, in bits per byte. This ranges from 0.0 (a single repeated
+    /// byte, or an empty sample) to 8.0 (all byte values equally frequent).
+    /// If the stream ends early the estimate is over the available bytes.
+    ///
+    /// ```
+    /// # use std::io::Read;
+    /// use peekread::{BufPeekReader, PeekRead};
+    /// let mut f = BufPeekReader::new(&[b'a'; 4096][..]);
+    /// assert!(f.peek_entropy_estimate(1024)? < 0.01);
+    ///
+    /// // Pseudo-random bytes from a xorshift generator.
+    /// let mut x = 0x2545f491u32;
+    /// let random: Vec<u8> = (0..65536)
+    ///     .map(|_| {
+    ///         x ^= x << 13;
+    ///         x ^= x >> 17;
+    ///         x ^= x << 5;
+    ///         x as u8
+    ///     })
+    ///     .collect();
+    /// let mut f = BufPeekReader::new(&random[..]);
+    /// assert!(f.peek_entropy_estimate(random.len())? > 7.99);
+    /// let mut slice = &random[..];
+    /// assert!(slice.peek_entropy_estimate(4096)? > 7.9);
+    /// assert_eq!(slice.len(), random.len());
+    /// // The read cursor didn't move.
+    /// let mut all = Vec::new();
+    /// f.read_to_end(&mut all)?;
+    /// assert_eq!(all, random);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    fn peek_entropy_estimate(&mut self, sample: usize) -> Result<f64> {
+        let mut counts = [0u64; 256];
+        let mut peeker = self.peek().take(sample as u64);
+        loop {
+            let chunk = match peeker.fill_buf() {
+                Ok(chunk) => chunk,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            if chunk.is_empty() {
+                break;
+            }
+
+            for &byte in chunk {
+                counts[byte as usize] += 1;
+            }
+            let n = chunk.len();
+            peeker.consume(n);
+        }
+
+        Ok(util::shannon_entropy(&counts))
+    }
+
+    /// Returns the length of the first complete JSON value in the stream if
+    /// it lies within the next `max` bytes, or `None` otherwise, without
+    /// advancing the stream. The length includes any leading whitespace, so
+    /// consuming that many bytes consumes exactly the value.
+    ///
+    /// This is a structural scan rather than a parser, it only tracks nesting
+    /// of objects, arrays and strings (including escapes) and does not
+    /// validate the value. Bare scalars such as numbers end at the first
+    /// whitespace or structural character following them, or at the end of
+    /// the stream.
+    ///
+    /// ```
+    /// use peekread::PeekRead;
+    /// let mut f = &br#"{"a": ["}"]}
+    /// {"b": 1"#[..];
+    /// assert_eq!(f.peek_json_value_len(1024)?, Some(12));
+    /// f = &f[13..];
+    /// assert_eq!(f.peek_json_value_len(1024)?, None);
+    /// assert_eq!((&b"1234,"[..]).peek_json_value_len(4)?, Some(4));
+    /// assert_eq!((&b"] 1"[..]).peek_json_value_len(1024)?, None);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    fn peek_json_value_len(&mut self, max: u64) -> Result<Option<u64>> {
+        let mut offset = 0u64;
+        let mut started = false;
+        let mut in_scalar = false;
+        let mut in_string = false;
+        let mut escaped = false;
+        let mut depth = 0u64;
+        let mut peeker = self.peek();
+        loop {
+            let chunk = match peeker.fill_buf() {
+                Ok(chunk) => chunk,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            if chunk.is_empty() {
+                return Ok(if in_scalar { Some(offset) } else { None });
+            }
+
+            for &byte in chunk {
+                if in_scalar && (byte.is_ascii_whitespace() || b",:[]{}\"".contains(&byte)) {
+                    return Ok(Some(offset));
+                }
+                if offset == max {
+                    return Ok(None);
+                }
+
+                if in_string {
+                    if escaped {
+                        escaped = false;
+                    } else if byte == b'\\' {
+                        escaped = true;
+                    } else if byte == b'"' {
+                        in_string = false;
+                        if depth == 0 {
+                            return Ok(Some(offset + 1));
+                        }
+                    }
+                } else if in_scalar {
+                    // Part of the scalar.
+                } else if !started && byte.is_ascii_whitespace() {
+                    // Skip leading whitespace.
+                } else {
+                    started = true;
+                    match byte {
+                        b'"' => in_string = true,
+                        b'{' | b'[' => depth += 1,
+                        // A closer without an opener is not a value.
+                        b'}' | b']' if depth == 0 => return Ok(None),
+                        b'}' | b']' => depth -= 1,
+                        _ => in_scalar = depth == 0,
+                    }
+                    if depth == 0 && !in_string && !in_scalar {
+                        return Ok(Some(offset + 1));
+                    }
+                }
+
+                offset += 1;
+            }
+
+            let n = chunk.len();
+            peeker.consume(n);
+        }
+    }
 }
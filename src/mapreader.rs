@@ -0,0 +1,132 @@
+use std::fmt::{Debug, Error as FmtError, Formatter};
+use std::io::{BufRead, Read, Result, Seek, SeekFrom};
+
+use crate::{
+    detail::{PeekCursorState, PeekReadImpl},
+    PeekCursor, PeekRead,
+};
+
+type FmtResult = std::result::Result<(), FmtError>;
+
+/// A wrapper for a [`PeekRead`] stream that applies a function to every byte
+/// of the stream.
+///
+/// The function is applied to both peeked and read bytes, so this is a lens
+/// on the stream rather than just a peeking aid. E.g. mapping with
+/// [`u8::to_ascii_lowercase`] allows case-insensitive matching with
+/// [`PeekRead::starts_with`], but also lowercases everything you read.
+///
+/// ```
+/// use peekread::{MapBytesPeekReader, PeekRead};
+/// let mut f = MapBytesPeekReader::new(&b"GET / HTTP/1.1"[..], |b: u8| b.to_ascii_lowercase());
+/// assert!(f.starts_with("get ")?);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub struct MapBytesPeekReader<R, F> {
+    inner: R,
+    f: F,
+}
+
+impl<R: PeekRead, F: Fn(u8) -> u8> MapBytesPeekReader<R, F> {
+    /// Creates a new [`MapBytesPeekReader`] applying `f` to each byte.
+    pub fn new(reader: R, f: F) -> Self {
+        Self { inner: reader, f }
+    }
+
+    /// Gets a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Gets a mutable reference to the underlying reader.
+    ///
+    /// Bytes read directly from the underlying reader are not mapped.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Unwraps this `MapBytesPeekReader<R, F>`, returning the underlying
+    /// reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn map_bytes(&self, buf: &mut [u8]) {
+        for byte in buf {
+            *byte = (self.f)(*byte);
+        }
+    }
+}
+
+impl<R: PeekRead, F: Fn(u8) -> u8> Read for MapBytesPeekReader<R, F> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let written = self.inner.read(buf)?;
+        self.map_bytes(&mut buf[..written]);
+        Ok(written)
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<()> {
+        self.inner.read_exact(buf)?;
+        self.map_bytes(buf);
+        Ok(())
+    }
+}
+
+impl<R: PeekRead, F: Fn(u8) -> u8> PeekRead for MapBytesPeekReader<R, F> {
+    fn peek(&mut self) -> PeekCursor<'_> {
+        PeekCursor::new(self)
+    }
+
+    fn buffered_lookahead(&self) -> usize {
+        self.inner.buffered_lookahead()
+    }
+}
+
+impl<R: PeekRead, F: Fn(u8) -> u8> PeekReadImpl for MapBytesPeekReader<R, F> {
+    fn peek_seek(&mut self, state: &mut PeekCursorState, pos: SeekFrom) -> Result<u64> {
+        let mut peeker = self.inner.peek();
+        peeker.seek(SeekFrom::Start(state.peek_pos))?;
+        state.peek_pos = peeker.seek(pos)?;
+        Ok(state.peek_pos)
+    }
+
+    fn peek_read(&mut self, state: &mut PeekCursorState, buf: &mut [u8]) -> Result<usize> {
+        let mut peeker = self.inner.peek();
+        peeker.seek(SeekFrom::Start(state.peek_pos))?;
+        let written = peeker.read(buf)?;
+        drop(peeker);
+        self.map_bytes(&mut buf[..written]);
+        state.peek_pos += written as u64;
+        Ok(written)
+    }
+
+    fn peek_fill_buf<'a>(&'a mut self, state: &'a mut PeekCursorState) -> Result<&'a [u8]> {
+        let mut peeker = self.inner.peek();
+        peeker.seek(SeekFrom::Start(state.peek_pos))?;
+        state.buf.clear();
+        state.buf.extend_from_slice(peeker.fill_buf()?);
+        drop(peeker);
+        self.map_bytes(&mut state.buf);
+        Ok(&state.buf)
+    }
+
+    fn peek_consume(&mut self, state: &mut PeekCursorState, amt: usize) {
+        state.peek_pos += amt as u64;
+    }
+
+    fn peek_commit(&mut self, state: &mut PeekCursorState) -> Result<()> {
+        let mut peeker = self.inner.peek();
+        peeker.seek(SeekFrom::Start(state.peek_pos))?;
+        peeker.commit()?;
+        state.peek_pos = 0;
+        Ok(())
+    }
+}
+
+impl<R: Debug, F> Debug for MapBytesPeekReader<R, F> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("MapBytesPeekReader")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
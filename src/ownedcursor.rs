@@ -0,0 +1,100 @@
+use std::io::{BufRead, Read, Result, Seek, SeekFrom};
+
+#[cfg(doc)]
+use crate::PeekCursor;
+use crate::PeekRead;
+
+/// A peek cursor that owns its stream, see [`PeekRead::into_peek`].
+///
+/// Unlike a [`PeekCursor`] this can be returned from the function that
+/// created the stream. It keeps its own peek position and opens a short-lived
+/// [`PeekCursor`] on the stream for every operation that needs data, so each
+/// of those pays the cost of starting a peek, e.g. a seek for
+/// [`SeekPeekReader`](crate::SeekPeekReader). Reading through
+/// [`BufRead::fill_buf`] amortizes this.
+#[derive(Debug)]
+pub struct OwnedPeekCursor<R> {
+    reader: R,
+    // The peek position, which is where buf[buf_pos] is in the stream.
+    peek_pos: u64,
+    buf: Vec<u8>,
+    buf_pos: usize,
+}
+
+impl<R: PeekRead> OwnedPeekCursor<R> {
+    pub(crate) fn new(reader: R) -> Self {
+        Self {
+            reader,
+            peek_pos: 0,
+            buf: Vec::new(),
+            buf_pos: 0,
+        }
+    }
+
+    /// Gets a reference to the underlying stream.
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    /// Unwraps this cursor, returning the stream with its read cursor where
+    /// it was when [`PeekRead::into_peek`] was called.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    fn discard_buf(&mut self) {
+        self.buf.clear();
+        self.buf_pos = 0;
+    }
+}
+
+impl<R: PeekRead> Read for OwnedPeekCursor<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if self.buf_pos == self.buf.len() {
+            // Nothing buffered, read straight into buf.
+            let mut peeker = self.reader.peek();
+            peeker.seek(SeekFrom::Start(self.peek_pos))?;
+            let read = peeker.read(buf)?;
+            self.peek_pos += read as u64;
+            return Ok(read);
+        }
+
+        let read = self.fill_buf()?.read(buf).unwrap(); // Can't fail.
+        self.consume(read);
+        Ok(read)
+    }
+}
+
+impl<R: PeekRead> BufRead for OwnedPeekCursor<R> {
+    fn fill_buf(&mut self) -> Result<&[u8]> {
+        if self.buf_pos == self.buf.len() {
+            self.discard_buf();
+            let mut peeker = self.reader.peek();
+            peeker.seek(SeekFrom::Start(self.peek_pos))?;
+            self.buf.extend_from_slice(peeker.fill_buf()?);
+        }
+        Ok(&self.buf[self.buf_pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        let amt = amt.min(self.buf.len() - self.buf_pos);
+        self.buf_pos += amt;
+        self.peek_pos += amt as u64;
+    }
+}
+
+impl<R: PeekRead> Seek for OwnedPeekCursor<R> {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        let mut peeker = self.reader.peek();
+        peeker.seek(SeekFrom::Start(self.peek_pos))?;
+        let new_pos = peeker.seek(pos)?;
+        drop(peeker);
+        self.discard_buf();
+        self.peek_pos = new_pos;
+        Ok(new_pos)
+    }
+
+    fn stream_position(&mut self) -> Result<u64> {
+        Ok(self.peek_pos)
+    }
+}
@@ -1,30 +1,273 @@
-use crate::util::seek_add_offset;
+use crate::util::{label_error, seek_add_offset, vec_deque_slices};
 use crate::{
     detail::{PeekCursorState, PeekReadImpl},
     PeekCursor, PeekRead,
 };
+use std::collections::VecDeque;
+use std::convert::TryInto;
+#[cfg(feature = "read_buf")]
+use std::io::BorrowedCursor;
 use std::io::{Error, ErrorKind, Read, Result, Seek, SeekFrom};
 
+#[cfg(doc)]
+use crate::BufPeekReader;
+#[cfg(doc)]
+use std::io::BufRead;
+
 /// A wrapper for a [`Read`] + [`Seek`] stream that implements [`PeekRead`]
 /// using seeking.
+///
+/// Every peek starts at the read cursor as it is when [`PeekRead::peek`] is
+/// called, and the stream is restored to exactly that position afterwards, so
+/// reads and peeks can be freely interleaved.
+///
+/// ```
+/// # use std::io::{Cursor, Read};
+/// use peekread::{PeekRead, SeekPeekReader};
+/// let mut f = SeekPeekReader::new(Cursor::new("abcdef"));
+/// let mut buf = [0; 2];
+/// f.peek().read_exact(&mut buf)?;
+/// f.read_exact(&mut buf)?;
+/// assert_eq!(&buf, b"ab");
+/// f.peek().read_exact(&mut buf)?;
+/// assert_eq!(&buf, b"cd");
+/// f.read_exact(&mut buf)?;
+/// assert_eq!(&buf, b"cd");
+/// # Ok::<(), std::io::Error>(())
+/// ```
+///
+/// This also holds when peeking fails halfway:
+///
+/// ```
+/// # use std::io::{Cursor, Read};
+/// use peekread::{PeekRead, SeekPeekReader};
+/// let mut f = SeekPeekReader::new(Cursor::new("abc"));
+/// let mut peeker = f.peek();
+/// assert!(peeker.read_exact(&mut [0; 4]).is_err());
+/// let mut buf = [0; 2];
+/// peeker.read_exact(&mut buf)?;
+/// assert_eq!(&buf, b"ab");
+/// drop(peeker);
+/// let mut s = String::new();
+/// f.read_to_string(&mut s)?;
+/// assert_eq!(s, "abc");
+/// # Ok::<(), std::io::Error>(())
+/// ```
 #[derive(Debug)]
 pub struct SeekPeekReader<R> {
     inner: R,
+    // Data pushed back in front of the stream by unread.
+    unread_storage: VecDeque<u8>,
+    // Position of the inner stream where the active peek started, which is
+    // restored when the peek ends. Only set while a peek cursor has touched
+    // the stream.
     start_pos: Option<u64>,
+    // The end position of the inner stream, if caching it is enabled and it
+    // was looked up.
+    len_caching: bool,
+    cached_end_pos: Option<u64>,
+    // How much we read at once when peek_fill_buf is called.
+    peek_chunk_size: usize,
+    // Data of the inner stream starting at peek_cache_pos, kept across
+    // peeks. While serving peeks from it the inner stream isn't moved along,
+    // which inner_stale tracks.
+    peek_cache_size: usize,
+    peek_cache: Vec<u8>,
+    peek_cache_pos: u64,
+    inner_stale: bool,
+    label: String,
 }
 
 impl<R: Read + Seek> SeekPeekReader<R> {
+    const DEFAULT_PEEK_CHUNK_SIZE: usize = 8 * 1024;
+
     /// Creates a new [`SeekPeekReader`].
     ///
     /// When calling `.peek()` on this object the stream is restored to
     /// its original position when the [`PeekCursor`] is dropped using a seek.
+    /// The underlying stream isn't touched at all by peeks that don't go past
+    /// the data pushed back with [`Self::unread`], including peeks that
+    /// aren't used:
+    ///
+    /// ```
+    /// # use std::io::{Cursor, Read, Result, Seek, SeekFrom};
+    /// # // Counts the seeks on the stream.
+    /// # struct CountSeeks<'a>(Cursor<&'a [u8]>, usize);
+    /// # impl Read for CountSeeks<'_> {
+    /// #     fn read(&mut self, buf: &mut [u8]) -> Result<usize> { self.0.read(buf) }
+    /// # }
+    /// # impl Seek for CountSeeks<'_> {
+    /// #     fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+    /// #         self.1 += 1;
+    /// #         self.0.seek(pos)
+    /// #     }
+    /// # }
+    /// use peekread::{PeekRead, SeekPeekReader};
+    /// let mut f = SeekPeekReader::new(CountSeeks(Cursor::new(b"data"), 0));
+    /// drop(f.peek());
+    /// f.unread(b"x");
+    /// assert_eq!(f.peek_byte()?, Some(b'x'));
+    /// assert_eq!(f.get_ref().1, 0);
+    /// // Peeking into the stream does seek, to restore its position.
+    /// f.peek().read_exact(&mut [0; 2])?;
+    /// assert!(f.get_ref().1 > 0);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
     pub fn new(reader: R) -> Self {
         Self {
             inner: reader,
+            unread_storage: VecDeque::new(),
             start_pos: None,
+            len_caching: false,
+            cached_end_pos: None,
+            peek_chunk_size: Self::DEFAULT_PEEK_CHUNK_SIZE,
+            peek_cache_size: 0,
+            peek_cache: Vec::new(),
+            peek_cache_pos: 0,
+            inner_stale: false,
+            label: String::new(),
+        }
+    }
+
+    /// Creates a new [`SeekPeekReader`], checking that `reader` can actually
+    /// seek.
+    ///
+    /// Some streams implement [`Seek`] but fail when seeking at runtime, e.g.
+    /// a [`File`](std::fs::File) that is really a pipe. With [`Self::new`]
+    /// this only shows up as errors when peeking. See
+    /// [`HybridPeekReader`](crate::HybridPeekReader) to fall back to buffering
+    /// instead.
+    pub fn try_new(mut reader: R) -> Result<Self> {
+        reader
+            .stream_position()
+            .map_err(|e| Error::new(e.kind(), format!("stream is not seekable: {}", e)))?;
+        Ok(Self::new(reader))
+    }
+
+    /// Pushes the given data into the stream at the front, pushing the read
+    /// cursor back.
+    ///
+    /// The data is kept in a buffer in front of the underlying stream, and
+    /// is discarded when seeking the read cursor with [`Seek::seek`].
+    pub fn unread<B: AsRef<[u8]>>(&mut self, data: B) {
+        let data = data.as_ref();
+        self.unread_storage.reserve(data.len());
+        for byte in data.iter().copied().rev() {
+            self.unread_storage.push_front(byte);
         }
     }
 
+    /// Pushes the given text into the stream at the front, see
+    /// [`Self::unread`].
+    pub fn unread_str(&mut self, s: &str) {
+        self.unread(s);
+    }
+
+    /// Returns the number of bytes left after the read cursor, including data
+    /// pushed back with [`Self::unread`].
+    ///
+    /// This seeks to the end of the underlying stream and back to find its
+    /// length. The [`BufPeekReader`] can't offer this, as it can only learn the
+    /// length of a stream by reading all of it.
+    pub fn remaining_len(&mut self) -> Result<u64> {
+        let cur_pos = self.inner.stream_position()?;
+        let end_pos = self.inner.seek(SeekFrom::End(0))?;
+        self.inner.seek(SeekFrom::Start(cur_pos))?;
+        Ok(self.unread_storage.len() as u64 + end_pos.saturating_sub(cur_pos))
+    }
+
+    /// Sets whether the length of the underlying stream is remembered after a
+    /// peek cursor seeks relative to its end, so later end-relative peeks
+    /// don't have to seek to the end of the stream again. Off by default.
+    ///
+    /// The cached length is dropped when a peek reads past it or the read
+    /// cursor moves past it. If the stream can grow in other ways, call
+    /// [`Self::refresh_len`] to look up the length again.
+    ///
+    /// ```
+    /// # use std::io::{Cursor, Read, Seek, SeekFrom};
+    /// use peekread::{PeekRead, SeekPeekReader};
+    /// let mut f = SeekPeekReader::new(Cursor::new(b"header...trailer".to_vec()));
+    /// f.set_len_caching(true);
+    /// assert_eq!(f.peek().seek(SeekFrom::End(-7))?, 9);
+    /// f.get_mut().get_mut().extend_from_slice(b"!");
+    /// assert_eq!(f.peek().seek(SeekFrom::End(-7))?, 9);
+    /// f.refresh_len();
+    /// assert_eq!(f.peek().seek(SeekFrom::End(-7))?, 10);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn set_len_caching(&mut self, enabled: bool) {
+        self.len_caching = enabled;
+        self.cached_end_pos = None;
+    }
+
+    /// Gets whether the stream length is cached. See
+    /// [`Self::set_len_caching`].
+    pub fn len_caching(&self) -> bool {
+        self.len_caching
+    }
+
+    /// Forgets the cached length of the underlying stream, so that the next
+    /// end-relative peek looks it up again. See [`Self::set_len_caching`].
+    pub fn refresh_len(&mut self) {
+        self.cached_end_pos = None;
+    }
+
+    /// Sets how many bytes [`BufRead::fill_buf`] on a peek cursor reads (and
+    /// seeks back over) at once. Larger chunks mean fewer reads and seeks
+    /// when peeking through [`BufRead`] methods, smaller chunks read less
+    /// data that isn't needed. Defaults to 8 KiB, sizes below 1 are treated
+    /// as 1.
+    pub fn set_peek_chunk_size(&mut self, nbytes: usize) {
+        self.peek_chunk_size = nbytes;
+    }
+
+    /// Gets the peek chunk size. See [`Self::set_peek_chunk_size`].
+    pub fn peek_chunk_size(&self) -> usize {
+        self.peek_chunk_size
+    }
+
+    /// Sets how many bytes read from the underlying stream by peeks are kept
+    /// in memory across peeks, 0 (the default) disables this. Later peeks
+    /// are served from memory where possible and only seek and read the
+    /// stream beyond that, e.g. when trying several parsers on the same
+    /// stream on slow media. Changing the size discards the cached data.
+    ///
+    /// The cache holds the first bytes peeked and is only started again once
+    /// the read cursor has moved past it. The underlying stream must not
+    /// change while data from it is cached.
+    ///
+    /// ```
+    /// # use std::io::{Cursor, Read};
+    /// use peekread::{PeekRead, SeekPeekReader};
+    /// let mut f = SeekPeekReader::new(Cursor::new(b"GIF89a...".to_vec()));
+    /// f.set_peek_cache_size(1024);
+    /// assert!(!f.starts_with(b"\x89PNG")?);
+    /// // This is served from memory.
+    /// assert!(f.starts_with("GIF89a")?);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn set_peek_cache_size(&mut self, nbytes: usize) {
+        self.peek_cache_size = nbytes;
+        self.peek_cache = Vec::new();
+    }
+
+    /// Gets the peek cache size. See [`Self::set_peek_cache_size`].
+    pub fn peek_cache_size(&self) -> usize {
+        self.peek_cache_size
+    }
+
+    /// Sets a label for this reader which is included in the messages of
+    /// errors produced by this crate, to tell streams apart. Empty by default.
+    pub fn set_label(&mut self, label: impl Into<String>) {
+        self.label = label.into();
+    }
+
+    /// Gets the label of this reader. See [`Self::set_label`].
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
     /// Gets a reference to the underlying reader.
     ///
     /// It is inadvisable to directly read from the underlying reader.
@@ -40,6 +283,9 @@ impl<R: Read + Seek> SeekPeekReader<R> {
     }
 
     /// Unwraps this `SeekPeekReader<R>`, returning the underlying reader.
+    ///
+    /// Any data pushed back with [`Self::unread`] that wasn't read yet is
+    /// lost.
     pub fn into_inner(self) -> R {
         self.inner
     }
@@ -52,10 +298,124 @@ impl<R: Read + Seek> SeekPeekReader<R> {
         self.start_pos = Some(start_pos);
         Ok(start_pos)
     }
+
+    // Seeks the inner stream back to where the active peek started, ending
+    // the peek.
+    fn restore_start_pos(&mut self) -> Result<()> {
+        if let Some(start_pos) = self.start_pos {
+            loop {
+                match self.inner.seek(SeekFrom::Start(start_pos)) {
+                    Ok(_) => break,
+                    Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(label_error(&self.label, e)),
+                }
+            }
+            self.start_pos = None;
+        }
+        self.inner_stale = false;
+        Ok(())
+    }
+
+    // Finds the end position of the inner stream, using the cached one if it
+    // is still plausible. Leaves the inner stream at an unspecified position.
+    fn end_pos(&mut self, start_pos: u64) -> Result<u64> {
+        match self.cached_end_pos {
+            Some(end_pos) if end_pos >= start_pos => Ok(end_pos),
+            _ => {
+                let end_pos = self.inner.seek(SeekFrom::End(0))?;
+                if self.len_caching {
+                    self.cached_end_pos = Some(end_pos);
+                }
+                Ok(end_pos)
+            }
+        }
+    }
+
+    // Drops the cached end position if a peek starting at start_pos read up
+    // to peek_end and the inner stream turned out to be longer.
+    fn check_cached_end_pos(&mut self, start_pos: u64, peek_end: u64) {
+        let inner_pos = start_pos + peek_end.saturating_sub(self.unread_storage.len() as u64);
+        if self
+            .cached_end_pos
+            .is_some_and(|end_pos| inner_pos > end_pos)
+        {
+            self.cached_end_pos = None;
+        }
+    }
+
+    // Moves the inner stream to where the peek position is. Peek positions
+    // inside the unread data leave the inner stream at the start position.
+    fn sync_inner_to_peek_pos(&mut self, peek_pos: u64) -> Result<()> {
+        if self.start_pos.is_none() && peek_pos <= self.unread_storage.len() as u64 {
+            self.inner_stale = false;
+            return Ok(()); // The inner stream was never moved.
+        }
+
+        let start_pos = self.init_start_pos()?;
+        let inner_offset = peek_pos.saturating_sub(self.unread_storage.len() as u64);
+        self.inner.seek(SeekFrom::Start(start_pos + inner_offset))?;
+        self.inner_stale = false;
+        Ok(())
+    }
+
+    // The position in the inner stream of a peek position past the unread
+    // data.
+    fn inner_peek_pos(&mut self, peek_pos: u64) -> Result<u64> {
+        let start_pos = self.init_start_pos()?;
+        Ok(start_pos + (peek_pos - self.unread_storage.len() as u64))
+    }
+
+    // The cached data starting at the given position of the inner stream.
+    fn cached_at(&self, inner_pos: u64) -> &[u8] {
+        inner_pos
+            .checked_sub(self.peek_cache_pos)
+            .and_then(|offset| self.peek_cache.get(offset.try_into().ok()?..))
+            .unwrap_or_default()
+    }
+
+    // Adds data read from the inner stream at inner_pos to the cache if it
+    // continues the cached data, starting the cache over if the read cursor
+    // has moved past it.
+    fn add_to_cache(&mut self, inner_pos: u64, data: &[u8]) {
+        let cache_end = self.peek_cache_pos + self.peek_cache.len() as u64;
+        if self.peek_cache.is_empty() || self.start_pos.is_some_and(|pos| pos >= cache_end) {
+            self.peek_cache.clear();
+            self.peek_cache_pos = inner_pos;
+        }
+        if inner_pos == self.peek_cache_pos + self.peek_cache.len() as u64 {
+            let room = self.peek_cache_size.saturating_sub(self.peek_cache.len());
+            self.peek_cache
+                .extend_from_slice(&data[..data.len().min(room)]);
+        }
+    }
+
+    // Reads from the inner stream at a peek position past the unread data,
+    // going through the peek cache.
+    fn peek_inner(&mut self, peek_pos: u64, buf: &mut [u8]) -> Result<usize> {
+        let inner_pos = self.inner_peek_pos(peek_pos)?;
+        let mut cached = self.cached_at(inner_pos);
+        if !cached.is_empty() {
+            let read = cached.read(buf).unwrap(); // Can't fail.
+            self.inner_stale = true;
+            return Ok(read);
+        }
+
+        if self.inner_stale {
+            self.sync_inner_to_peek_pos(peek_pos)?;
+        }
+        let read = self.inner.read(buf)?;
+        let start_pos = self.init_start_pos()?;
+        self.check_cached_end_pos(start_pos, peek_pos + read as u64);
+        if self.peek_cache_size > 0 {
+            self.add_to_cache(inner_pos, &buf[..read]);
+        }
+        Ok(read)
+    }
 }
 
 impl<R: Seek + Read> Seek for SeekPeekReader<R> {
     fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        self.unread_storage.clear();
         self.inner.seek(pos)
     }
 
@@ -66,19 +426,59 @@ impl<R: Seek + Read> Seek for SeekPeekReader<R> {
 
 impl<R: Seek + Read> Read for SeekPeekReader<R> {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
-        self.inner.read(buf)
+        let (mut first, mut second) = self.unread_storage.as_slices();
+        let mut written = first.read(buf).unwrap(); // Can't fail.
+        written += second.read(&mut buf[written..]).unwrap(); // Can't fail.
+        self.inner.read(&mut buf[written..]).map(|inner_written| {
+            self.unread_storage.drain(..written);
+            written + inner_written
+        })
     }
 
     fn read_exact(&mut self, buf: &mut [u8]) -> Result<()> {
-        self.inner.read_exact(buf)
+        let (mut first, mut second) = self.unread_storage.as_slices();
+        let mut written = first.read(buf).unwrap(); // Can't fail.
+        written += second.read(&mut buf[written..]).unwrap(); // Can't fail.
+        self.inner.read_exact(&mut buf[written..]).map(|_| {
+            self.unread_storage.drain(..written);
+        })
+    }
+
+    #[cfg(feature = "read_buf")]
+    fn read_buf(&mut self, mut cursor: BorrowedCursor<'_>) -> Result<()> {
+        if self.unread_storage.is_empty() {
+            return self.inner.read_buf(cursor);
+        }
+
+        let (first, second) = self.unread_storage.as_slices();
+        let mut written = 0;
+        for slice in [first, second] {
+            let n = slice.len().min(cursor.capacity());
+            cursor.append(&slice[..n]);
+            written += n;
+        }
+        self.unread_storage.drain(..written);
+        Ok(())
     }
 
     fn read_to_end(&mut self, buf: &mut Vec<u8>) -> Result<usize> {
-        self.inner.read_to_end(buf)
+        let written = self.unread_storage.len();
+        buf.extend(self.unread_storage.drain(..));
+        Ok(written + self.inner.read_to_end(buf)?)
     }
 
     fn read_to_string(&mut self, buf: &mut String) -> Result<usize> {
-        self.inner.read_to_string(buf)
+        if self.unread_storage.is_empty() {
+            return self.inner.read_to_string(buf);
+        }
+
+        let mut bytes = Vec::new();
+        self.read_to_end(&mut bytes)?;
+        let s = String::from_utf8(bytes).map_err(|_| {
+            Error::new(ErrorKind::InvalidData, "stream did not contain valid UTF-8")
+        })?;
+        buf.push_str(&s);
+        Ok(s.len())
     }
 }
 
@@ -87,73 +487,218 @@ impl<R: Read + Seek> PeekRead for SeekPeekReader<R> {
         self.start_pos = None;
         PeekCursor::new(self)
     }
+
+    fn buffered_lookahead(&self) -> usize {
+        // Only data pushed back with unread is in memory.
+        self.unread_storage.len()
+    }
+
+    fn skip(&mut self, n: u64) -> Result<u64> {
+        let unread_skipped = (self.unread_storage.len() as u64).min(n);
+        self.unread_storage.drain(..unread_skipped as usize);
+
+        let cur_pos = self.inner.stream_position()?;
+        let end_pos = self.inner.seek(SeekFrom::End(0))?.max(cur_pos);
+        let new_pos = cur_pos.saturating_add(n - unread_skipped).min(end_pos);
+        self.inner.seek(SeekFrom::Start(new_pos))?;
+        Ok(unread_skipped + new_pos - cur_pos)
+    }
 }
 
 impl<R: Read + Seek> PeekReadImpl for SeekPeekReader<R> {
-    fn peek_seek(&mut self, _state: &mut PeekCursorState, pos: SeekFrom) -> Result<u64> {
-        let start_pos = self.init_start_pos()?;
-        let cur_pos = self.stream_position()?;
+    fn peek_seek(&mut self, state: &mut PeekCursorState, pos: SeekFrom) -> Result<u64> {
         let new_pos = match pos {
-            SeekFrom::Start(offset) => self.inner.seek(SeekFrom::Start(start_pos + offset))?,
-            SeekFrom::Current(offset) => self.inner.seek(SeekFrom::Current(offset))?,
+            SeekFrom::Start(offset) => Ok(offset),
+            SeekFrom::Current(offset) => seek_add_offset(state.peek_pos, offset),
             SeekFrom::End(offset) => {
-                // TODO: can this be more efficient?
-                let end_pos = self.inner.seek(SeekFrom::End(0))?.max(start_pos);
-                match seek_add_offset(end_pos, offset) {
-                    Ok(o) => self.inner.seek(SeekFrom::Start(o))?,
-                    Err(e) => {
-                        // Restore position.
-                        self.inner.seek(SeekFrom::Start(cur_pos))?;
-                        return Err(e);
-                    }
-                }
+                let start_pos = self.init_start_pos()?;
+                let end_pos = self.end_pos(start_pos)?.max(start_pos);
+                let end = self.unread_storage.len() as u64 + (end_pos - start_pos);
+                seek_add_offset(end, offset)
             }
         };
 
-        if new_pos < start_pos {
-            self.inner.seek(SeekFrom::Start(cur_pos))?;
-            Err(Error::new(
-                ErrorKind::InvalidInput,
-                "invalid seek to a negative or overflowing position",
-            ))
-        } else {
-            Ok(new_pos - start_pos)
+        match new_pos {
+            Ok(new_pos) => {
+                self.sync_inner_to_peek_pos(new_pos)?;
+                state.peek_pos = new_pos;
+                Ok(new_pos)
+            }
+            Err(e) => {
+                // Restore position.
+                self.sync_inner_to_peek_pos(state.peek_pos)?;
+                Err(label_error(&self.label, e))
+            }
         }
     }
 
-    fn peek_read(&mut self, _state: &mut PeekCursorState, buf: &mut [u8]) -> Result<usize> {
-        self.init_start_pos()?;
-        let written = self.inner.read(buf)?;
+    fn peek_read(&mut self, state: &mut PeekCursorState, buf: &mut [u8]) -> Result<usize> {
+        let (mut first, mut second) = vec_deque_slices(&self.unread_storage, state.peek_pos);
+        let mut written = first.read(buf).unwrap(); // Can't fail.
+        written += second.read(&mut buf[written..]).unwrap(); // Can't fail.
+        if written == 0 {
+            written = self.peek_inner(state.peek_pos, buf)?;
+        }
+        state.peek_pos += written as u64;
         Ok(written)
     }
 
-    fn peek_read_exact(&mut self, _state: &mut PeekCursorState, buf: &mut [u8]) -> Result<()> {
-        self.init_start_pos()?;
-        self.inner.read_exact(buf)?;
+    #[cfg(feature = "read_buf")]
+    fn peek_read_buf(
+        &mut self,
+        state: &mut PeekCursorState,
+        mut cursor: BorrowedCursor<'_>,
+    ) -> Result<()> {
+        let (first, second) = vec_deque_slices(&self.unread_storage, state.peek_pos);
+        let mut written = 0;
+        for slice in [first, second] {
+            let n = slice.len().min(cursor.capacity());
+            cursor.append(&slice[..n]);
+            written += n;
+        }
+        if written == 0 {
+            let inner_pos = self.inner_peek_pos(state.peek_pos)?;
+            let cached = self.cached_at(inner_pos);
+            if !cached.is_empty() {
+                let n = cached.len().min(cursor.capacity());
+                cursor.append(&cached[..n]);
+                self.inner_stale = true;
+                state.peek_pos += n as u64;
+                return Ok(());
+            }
+            if self.inner_stale {
+                self.sync_inner_to_peek_pos(state.peek_pos)?;
+            }
+            let start_pos = self.init_start_pos()?;
+            let capacity = cursor.capacity();
+            self.inner.read_buf(cursor.reborrow())?;
+            written = capacity - cursor.capacity();
+            self.check_cached_end_pos(start_pos, state.peek_pos + written as u64);
+        }
+        state.peek_pos += written as u64;
+        Ok(())
+    }
+
+    fn peek_read_exact(&mut self, state: &mut PeekCursorState, buf: &mut [u8]) -> Result<()> {
+        if self.peek_cache_size > 0 {
+            // Read piece by piece, so that cached data is used.
+            let peek_pos = state.peek_pos;
+            let mut filled = 0;
+            while filled < buf.len() {
+                let result = match self.peek_read(state, &mut buf[filled..]) {
+                    Ok(0) => Err(Error::from(ErrorKind::UnexpectedEof)),
+                    result => result,
+                };
+                match result {
+                    Ok(read) => filled += read,
+                    Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                    Err(e) => {
+                        state.peek_pos = peek_pos;
+                        self.sync_inner_to_peek_pos(peek_pos)?;
+                        return Err(e);
+                    }
+                }
+            }
+            return Ok(());
+        }
+
+        let (mut first, mut second) = vec_deque_slices(&self.unread_storage, state.peek_pos);
+        let mut written = first.read(buf).unwrap(); // Can't fail.
+        written += second.read(&mut buf[written..]).unwrap(); // Can't fail.
+        if written < buf.len() {
+            let start_pos = self.init_start_pos()?;
+            if let Err(e) = self.inner.read_exact(&mut buf[written..]) {
+                // How much was read is unspecified, move back to where we were.
+                self.sync_inner_to_peek_pos(state.peek_pos)?;
+                return Err(e);
+            }
+            self.check_cached_end_pos(start_pos, state.peek_pos + buf.len() as u64);
+        }
+        state.peek_pos += buf.len() as u64;
         Ok(())
     }
 
     fn peek_fill_buf<'a>(&'a mut self, state: &'a mut PeekCursorState) -> Result<&'a [u8]> {
-        self.init_start_pos()?;
-        // With specialization we could provide a more optimal fill_buf here.
+        if state.peek_pos < self.unread_storage.len() as u64 {
+            let (first, second) = vec_deque_slices(&self.unread_storage, state.peek_pos);
+            return Ok(if !first.is_empty() { first } else { second });
+        }
+
+        let inner_pos = self.inner_peek_pos(state.peek_pos)?;
+        if !self.cached_at(inner_pos).is_empty() {
+            self.inner_stale = true;
+            return Ok(self.cached_at(inner_pos));
+        }
+
+        if self.inner_stale {
+            self.sync_inner_to_peek_pos(state.peek_pos)?;
+        }
+        let start_pos = self.init_start_pos()?;
+        state.buf.resize(self.peek_chunk_size.max(1), 0);
         let read = self.inner.read(&mut state.buf)?;
         self.inner.seek(SeekFrom::Current(-(read as i64)))?;
+        self.check_cached_end_pos(start_pos, state.peek_pos + read as u64);
+        if self.peek_cache_size > 0 {
+            self.add_to_cache(inner_pos, &state.buf[..read]);
+        }
         Ok(&state.buf[..read])
     }
 
-    fn peek_consume(&mut self, _state: &mut PeekCursorState, amt: usize) {
-        self.init_start_pos().ok();
-        // With specialization we could provide a more optimal fill_buf here.
-        self.inner.seek(SeekFrom::Current(amt as i64)).ok();
+    fn peek_fill_buf_at_least<'a>(
+        &'a mut self,
+        state: &'a mut PeekCursorState,
+        n: usize,
+    ) -> Result<&'a [u8]> {
+        // Read at least n bytes into the scratch buffer and seek back.
+        let peek_pos = state.peek_pos;
+        let mut buf = std::mem::take(&mut state.buf);
+        buf.resize(n.max(self.peek_chunk_size).max(1), 0);
+        let mut filled = 0;
+        let result = loop {
+            if filled >= n.max(1) {
+                break Ok(());
+            }
+            match self.peek_read(state, &mut buf[filled..]) {
+                Ok(0) => break Ok(()),
+                Ok(read) => filled += read,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => break Err(e),
+            }
+        };
+        state.peek_pos = peek_pos;
+        state.buf = buf;
+        self.sync_inner_to_peek_pos(peek_pos)?;
+        result?;
+        Ok(&state.buf[..filled])
     }
 
-    fn peek_drop(&mut self, _state: &mut PeekCursorState) {
-        if let Some(start_pos) = self.start_pos {
-            while let Err(e) = self.inner.seek(SeekFrom::Start(start_pos)) {
-                if e.kind() != std::io::ErrorKind::Interrupted {
-                    break;
-                }
-            }
+    fn peek_consume(&mut self, state: &mut PeekCursorState, amt: usize) {
+        state.peek_pos += amt as u64;
+        if self.peek_cache_size > 0 {
+            // Only move the inner stream once it is read from again.
+            self.inner_stale = true;
+        } else {
+            self.sync_inner_to_peek_pos(state.peek_pos).ok();
         }
     }
+
+    fn peek_commit(&mut self, state: &mut PeekCursorState) -> Result<()> {
+        self.sync_inner_to_peek_pos(state.peek_pos)?;
+        let unread_len = self.unread_storage.len() as u64;
+        self.unread_storage
+            .drain(..state.peek_pos.min(unread_len) as usize);
+        if self.start_pos.is_some() {
+            self.start_pos = Some(self.inner.stream_position()?);
+        }
+        state.peek_pos = 0;
+        Ok(())
+    }
+
+    fn peek_finish(&mut self, _state: &mut PeekCursorState) -> Result<()> {
+        self.restore_start_pos()
+    }
+
+    fn peek_drop(&mut self, _state: &mut PeekCursorState) {
+        self.restore_start_pos().ok();
+    }
 }
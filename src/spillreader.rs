@@ -0,0 +1,260 @@
+use std::collections::VecDeque;
+use std::convert::TryInto;
+use std::fs::File;
+use std::io::{ErrorKind, Read, Result, Seek, SeekFrom, Write};
+
+use crate::util::{seek_add_offset, vec_deque_slices};
+use crate::{
+    detail::{PeekCursorState, PeekReadImpl},
+    PeekCursor, PeekRead,
+};
+
+/// A wrapper for a [`Read`] stream that implements [`PeekRead`] using a buffer
+/// to store peeked data, like [`BufPeekReader`](crate::BufPeekReader), but
+/// moves peeked data to a temporary file once the buffer reaches a memory
+/// limit.
+///
+/// This allows peeking arbitrarily far into streams that can't seek, e.g. a
+/// large upload, without holding all peeked data in memory. The first
+/// [`Self::memory_limit`] bytes after the read cursor that were peeked are
+/// kept in memory, anything peeked beyond that goes to the file, which is
+/// created when first needed and deleted when the reader is dropped. Reading
+/// takes data from memory, then from the file, then from the stream.
+///
+/// ```
+/// # use std::io::{Read, Seek, SeekFrom};
+/// use peekread::{PeekRead, SpillingPeekReader};
+/// let data: Vec<u8> = (0..100).collect();
+/// let mut f = SpillingPeekReader::new(&data[..]);
+/// f.set_memory_limit(16);
+/// let mut peeker = f.peek();
+/// peeker.seek(SeekFrom::End(-1))?;
+/// let mut last = [0];
+/// peeker.read_exact(&mut last)?;
+/// assert_eq!(last, [99]);
+/// drop(peeker);
+/// let mut all = Vec::new();
+/// f.read_to_end(&mut all)?;
+/// assert_eq!(all, data);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct SpillingPeekReader<R> {
+    // The peeked data starts in memory and continues in the spill file from
+    // spill_begin up to spill_end.
+    mem: VecDeque<u8>,
+    memory_limit: usize,
+    spill: Option<File>,
+    spill_begin: u64,
+    spill_end: u64,
+    inner: R,
+}
+
+impl<R: Read> SpillingPeekReader<R> {
+    const DEFAULT_MEMORY_LIMIT: usize = 1024 * 1024;
+    // How much we read from the stream or the spill file at once.
+    const CHUNK_SIZE: usize = 8 * 1024;
+
+    /// Creates a new [`SpillingPeekReader`] with a memory limit of 1 MiB.
+    pub fn new(reader: R) -> Self {
+        Self {
+            mem: VecDeque::new(),
+            memory_limit: Self::DEFAULT_MEMORY_LIMIT,
+            spill: None,
+            spill_begin: 0,
+            spill_end: 0,
+            inner: reader,
+        }
+    }
+
+    /// Sets how many bytes of peeked data are kept in memory before the rest
+    /// is moved to the temporary file. Data that is already buffered stays
+    /// where it is.
+    pub fn set_memory_limit(&mut self, nbytes: usize) {
+        self.memory_limit = nbytes;
+    }
+
+    /// Gets the memory limit. See [`Self::set_memory_limit`].
+    pub fn memory_limit(&self) -> usize {
+        self.memory_limit
+    }
+
+    /// Returns how many bytes of peeked data were moved to the temporary
+    /// file and not read yet.
+    pub fn spilled_len(&self) -> u64 {
+        self.spill_end - self.spill_begin
+    }
+
+    /// Gets a reference to the underlying reader.
+    ///
+    /// It is inadvisable to directly read from the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Gets a mutable reference to the underlying reader.
+    ///
+    /// It is inadvisable to directly read from the underlying reader.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Unwraps this `SpillingPeekReader<R>`, returning the underlying reader.
+    ///
+    /// Note that any buffered data is lost, and the temporary file deleted.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    // The number of buffered bytes, in memory and spilled.
+    fn buf_len(&self) -> u64 {
+        self.mem.len() as u64 + self.spilled_len()
+    }
+
+    // Appends data read from the stream to the buffered data, in memory if it
+    // fits and nothing was spilled, in the spill file otherwise.
+    fn append(&mut self, mut data: &[u8]) -> Result<()> {
+        if self.spilled_len() == 0 {
+            let fits = self.memory_limit.saturating_sub(self.mem.len());
+            let (in_mem, rest) = data.split_at(fits.min(data.len()));
+            self.mem.extend(in_mem);
+            data = rest;
+        }
+        if data.is_empty() {
+            return Ok(());
+        }
+
+        let spill = match &mut self.spill {
+            Some(spill) => spill,
+            None => self.spill.insert(tempfile::tempfile()?),
+        };
+        spill.seek(SeekFrom::Start(self.spill_end))?;
+        spill.write_all(data)?;
+        self.spill_end += data.len() as u64;
+        Ok(())
+    }
+
+    // Try to fill the buffer so that it's at least nbytes in length
+    // (may fail to do so if EOF is reached - no error is reported then).
+    fn request_buffer(&mut self, nbytes: u64) -> Result<()> {
+        if self.buf_len() >= nbytes {
+            return Ok(());
+        }
+
+        let mut chunk = vec![0; Self::CHUNK_SIZE];
+        while self.buf_len() < nbytes {
+            let needed = nbytes - self.buf_len();
+            let len = needed.try_into().unwrap_or(usize::MAX).min(chunk.len());
+            let read = match self.inner.read(&mut chunk[..len]) {
+                Ok(0) => break, // EOF.
+                Ok(read) => read,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            self.append(&chunk[..read])?;
+        }
+        Ok(())
+    }
+
+    // Copies buffered data at offset into buf, returning how many bytes were
+    // copied. Doesn't cross from memory into the spill file.
+    fn read_buffered(&mut self, offset: u64, buf: &mut [u8]) -> Result<usize> {
+        let mem_len = self.mem.len() as u64;
+        if offset < mem_len {
+            let (mut first, mut second) = vec_deque_slices(&self.mem, offset);
+            let mut written = first.read(buf).unwrap(); // Can't fail.
+            written += second.read(&mut buf[written..]).unwrap(); // Can't fail.
+            return Ok(written);
+        }
+
+        let pos = self.spill_begin + (offset - mem_len);
+        if pos >= self.spill_end {
+            return Ok(0);
+        }
+        let len = (self.spill_end - pos).min(buf.len() as u64) as usize;
+        let spill = self.spill.as_mut().unwrap(); // Can't fail, spill_end > 0.
+        spill.seek(SeekFrom::Start(pos))?;
+        spill.read_exact(&mut buf[..len])?;
+        Ok(len)
+    }
+
+    // Drops the first amt bytes of buffered data.
+    fn consume(&mut self, amt: u64) {
+        let from_mem = amt.min(self.mem.len() as u64) as usize;
+        self.mem.drain(..from_mem);
+        self.spill_begin = (self.spill_begin + (amt - from_mem as u64)).min(self.spill_end);
+        if self.spill_begin == self.spill_end {
+            // Start writing at the beginning of the file again.
+            self.spill_begin = 0;
+            self.spill_end = 0;
+        }
+    }
+}
+
+impl<R: Read> Read for SpillingPeekReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if self.buf_len() == 0 {
+            // Nothing buffered, read straight into buf.
+            return self.inner.read(buf);
+        }
+
+        let written = self.read_buffered(0, buf)?;
+        self.consume(written as u64);
+        Ok(written)
+    }
+}
+
+impl<R: Read> PeekRead for SpillingPeekReader<R> {
+    fn peek(&mut self) -> PeekCursor<'_> {
+        PeekCursor::new(self)
+    }
+
+    fn buffered_lookahead(&self) -> usize {
+        self.buf_len().try_into().unwrap_or(usize::MAX)
+    }
+}
+
+impl<R: Read> PeekReadImpl for SpillingPeekReader<R> {
+    fn peek_seek(&mut self, state: &mut PeekCursorState, pos: SeekFrom) -> Result<u64> {
+        state.peek_pos = match pos {
+            SeekFrom::Start(offset) => offset,
+            SeekFrom::Current(offset) => seek_add_offset(state.peek_pos, offset)?,
+            SeekFrom::End(offset) => {
+                self.request_buffer(u64::MAX)?;
+                seek_add_offset(self.buf_len(), offset)?
+            }
+        };
+        Ok(state.peek_pos)
+    }
+
+    fn peek_read(&mut self, state: &mut PeekCursorState, buf: &mut [u8]) -> Result<usize> {
+        self.request_buffer(state.peek_pos.saturating_add(buf.len() as u64))?;
+        let written = self.read_buffered(state.peek_pos, buf)?;
+        state.peek_pos += written as u64;
+        Ok(written)
+    }
+
+    fn peek_fill_buf<'a>(&'a mut self, state: &'a mut PeekCursorState) -> Result<&'a [u8]> {
+        self.request_buffer(state.peek_pos.saturating_add(1))?;
+        if state.peek_pos < self.mem.len() as u64 {
+            let (first, second) = vec_deque_slices(&self.mem, state.peek_pos);
+            return Ok(if !first.is_empty() { first } else { second });
+        }
+
+        state.buf.resize(Self::CHUNK_SIZE, 0);
+        let read = self.read_buffered(state.peek_pos, &mut state.buf)?;
+        Ok(&state.buf[..read])
+    }
+
+    fn peek_consume(&mut self, state: &mut PeekCursorState, amt: usize) {
+        state.peek_pos += amt as u64;
+    }
+
+    fn peek_commit(&mut self, state: &mut PeekCursorState) -> Result<()> {
+        // We may have seeked past the buffered data.
+        self.request_buffer(state.peek_pos)?;
+        self.consume(state.peek_pos);
+        state.peek_pos = 0;
+        Ok(())
+    }
+}
@@ -0,0 +1,27 @@
+use std::io::StdinLock;
+
+use crate::BufPeekReader;
+
+/// Returns a [`BufPeekReader`] over the locked standard input of the process.
+///
+/// Standard input can't seek, so peeked data is buffered and seen again by
+/// the next real read, like for any other [`BufPeekReader`]. Lock and wrap
+/// standard input only once, as data buffered by one reader is not visible
+/// to others.
+///
+/// ```no_run
+/// # use std::io::BufRead;
+/// use peekread::PeekRead;
+/// let mut input = peekread::stdin();
+/// if input.starts_with("{")? {
+///     // Parse JSON.
+/// } else {
+///     for line in input.lines() {
+///         // ...
+///     }
+/// }
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub fn stdin() -> BufPeekReader<StdinLock<'static>> {
+    BufPeekReader::new(std::io::stdin().lock())
+}
@@ -0,0 +1,57 @@
+use std::io::{Read, Result};
+
+use crate::BufPeekReader;
+
+/// A tentative read from a [`BufPeekReader`], see [`BufPeekReader::begin`].
+///
+/// Everything read through the transaction is kept until it ends. If the
+/// transaction is dropped without calling [`Self::commit`] the data is
+/// pushed back into the stream with [`BufPeekReader::unread`], as if it was
+/// never read.
+#[derive(Debug)]
+pub struct Transaction<'a, R: Read> {
+    reader: &'a mut BufPeekReader<R>,
+    consumed: Vec<u8>,
+    // The reader's amount of unread data when the transaction started.
+    unread_len: usize,
+}
+
+impl<'a, R: Read> Transaction<'a, R> {
+    pub(crate) fn new(reader: &'a mut BufPeekReader<R>) -> Self {
+        Self {
+            unread_len: reader.unread_len(),
+            reader,
+            consumed: Vec::new(),
+        }
+    }
+
+    /// Ends the transaction, keeping everything read through it consumed.
+    pub fn commit(mut self) {
+        self.consumed.clear();
+    }
+
+    /// Ends the transaction, pushing everything read through it back into the
+    /// stream. This is the same as dropping it.
+    pub fn rollback(self) {}
+
+    /// Returns the data read through this transaction so far.
+    pub fn consumed(&self) -> &[u8] {
+        &self.consumed
+    }
+}
+
+impl<'a, R: Read> Read for Transaction<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let read = self.reader.read(buf)?;
+        self.consumed.extend_from_slice(&buf[..read]);
+        Ok(read)
+    }
+}
+
+impl<'a, R: Read> Drop for Transaction<'a, R> {
+    fn drop(&mut self) {
+        if !self.consumed.is_empty() {
+            self.reader.restore_read(&self.consumed, self.unread_len);
+        }
+    }
+}
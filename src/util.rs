@@ -1,6 +1,26 @@
+use std::collections::VecDeque;
 use std::convert::TryInto;
-use std::io::{Error, ErrorKind, Result};
+use std::io::{Error, ErrorKind, Read, Result};
 
+#[cfg(doc)]
+use crate::detail::PeekReadImpl;
+use crate::PeekRead;
+#[cfg(doc)]
+use std::io::SeekFrom;
+
+/// Adds a relative `offset` to the position `current`, as needed to implement
+/// [`PeekReadImpl::peek_seek`] for [`SeekFrom::Current`] and
+/// [`SeekFrom::End`]. Results in an error of kind [`ErrorKind::InvalidInput`]
+/// if the new position would be negative or overflow, like the built-in
+/// implementations do.
+///
+/// ```
+/// # use std::io::ErrorKind;
+/// use peekread::detail::seek_add_offset;
+/// assert_eq!(seek_add_offset(10, -3)?, 7);
+/// assert_eq!(seek_add_offset(2, -3).unwrap_err().kind(), ErrorKind::InvalidInput);
+/// # Ok::<(), std::io::Error>(())
+/// ```
 pub fn seek_add_offset(current: u64, offset: i64) -> Result<u64> {
     current
         .try_into()
@@ -14,3 +34,108 @@ pub fn seek_add_offset(current: u64, offset: i64) -> Result<u64> {
             )
         })
 }
+
+// Prefixes an error produced by this crate with the label of the reader it
+// originated from, if any.
+pub fn label_error(label: &str, e: Error) -> Error {
+    if label.is_empty() {
+        e
+    } else {
+        Error::new(e.kind(), format!("{}: {}", label, e))
+    }
+}
+
+pub fn shannon_entropy(counts: &[u64; 256]) -> f64 {
+    let total: u64 = counts.iter().sum();
+    if total == 0 {
+        return 0.0;
+    }
+
+    counts
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let p = count as f64 / total as f64;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+// Checks whether the stream starts with bytes, comparing chunks with eq.
+pub fn peek_starts_with<R, F>(reader: &mut R, bytes: &[u8], eq: F) -> Result<bool>
+where
+    R: PeekRead + ?Sized,
+    F: Fn(&[u8], &[u8]) -> bool,
+{
+    read_starts_with(&mut reader.peek(), bytes, eq)
+}
+
+// Checks whether reading from peeker gives bytes, comparing chunks with eq.
+pub fn read_starts_with<R, F>(peeker: &mut R, bytes: &[u8], eq: F) -> Result<bool>
+where
+    R: Read + ?Sized,
+    F: Fn(&[u8], &[u8]) -> bool,
+{
+    let mut buf = [0u8; 32]; // Prevent allocation, check 32 bytes at a time.
+    for chunk in bytes.chunks(32) {
+        let partial_buf = &mut buf[..chunk.len()];
+        if let Err(e) = peeker.read_exact(partial_buf) {
+            return match e.kind() {
+                ErrorKind::UnexpectedEof => Ok(false),
+                _ => Err(e),
+            };
+        }
+
+        if !eq(partial_buf, chunk) {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
+// Counts how many leading bytes of the stream equal those of bytes.
+pub fn peek_common_prefix_len<R: PeekRead + ?Sized>(reader: &mut R, bytes: &[u8]) -> Result<usize> {
+    let mut peeker = reader.peek();
+    let mut buf = [0u8; 32]; // Prevent allocation, check 32 bytes at a time.
+    let mut matched = 0;
+    for chunk in bytes.chunks(32) {
+        let read = read_fully(&mut peeker, &mut buf[..chunk.len()])?;
+        let equal = buf[..read]
+            .iter()
+            .zip(chunk)
+            .take_while(|(a, b)| a == b)
+            .count();
+        matched += equal;
+        if equal < chunk.len() {
+            break;
+        }
+    }
+
+    Ok(matched)
+}
+
+// The data of a VecDeque starting from the peek position as two slices.
+pub fn vec_deque_slices(deque: &VecDeque<u8>, peek_pos: u64) -> (&[u8], &[u8]) {
+    let peek_pos = peek_pos.try_into().unwrap_or(usize::MAX);
+    let (a, b) = deque.as_slices();
+    let first = a.get(peek_pos..).unwrap_or_default();
+    let second = b
+        .get(peek_pos.saturating_sub(a.len())..)
+        .unwrap_or_default();
+    (first, second)
+}
+
+// Reads until buf is full or EOF is reached, returning the bytes read.
+pub fn read_fully<R: Read + ?Sized>(reader: &mut R, buf: &mut [u8]) -> Result<usize> {
+    let mut written = 0;
+    while written < buf.len() {
+        match reader.read(&mut buf[written..]) {
+            Ok(0) => break,
+            Ok(n) => written += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(written)
+}